@@ -0,0 +1,231 @@
+//! How a toast comes and goes: fading in, holding, then fading out, optionally sliding and scaling on the way.
+//!
+//! A `Timeline` is pure: it says what the toast looks like at any moment of its life as a `Frame`.
+//! `Timeline::play` keeps the time, so a display backend only has to put each frame on screen.
+use serde::Deserialize;
+use std::{thread, time::{Duration, Instant}};
+
+/// Time between frames, for about 60 a second.
+pub const FRAME: Duration = Duration::from_micros(16_667);
+
+/// Shapes how an animation moves from start to end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Easing {
+    Linear,
+    /// Starts slow, ends fast.
+    EaseIn,
+    /// Starts fast, ends slow.
+    #[default]
+    EaseOut,
+    EaseInOut,
+    /// Like `EaseOut`, but overshoots a little and settles back.
+    Back,
+}
+impl Easing {
+    /// How far along the animation is after `t` of it has gone, both from 0 to 1.
+    pub fn ease(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut if t < 0.5 => 4.0 * t * t * t,
+            Easing::EaseInOut => 1.0 - (2.0 - 2.0 * t).powi(3) / 2.0,
+            Easing::Back => {
+                let (c1, c3) = (1.70158, 2.70158);
+                1.0 + c3 * (t - 1.0).powi(3) + c1 * (t - 1.0).powi(2)
+            }
+        }
+    }
+}
+
+/// The side a toast slides in from, and back out to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Slide {
+    #[default]
+    None,
+    Left,
+    Right,
+    Up,
+    Down,
+}
+impl Slide {
+    fn direction(self) -> (f32, f32) {
+        match self {
+            Slide::None => (0.0, 0.0),
+            Slide::Left => (-1.0, 0.0),
+            Slide::Right => (1.0, 0.0),
+            Slide::Up => (0.0, -1.0),
+            Slide::Down => (0.0, 1.0),
+        }
+    }
+}
+
+/// The `[animation]` section of the config. How long the toast fades out for is `toast.fade_out`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Animation {
+    /// Seconds to fade in.
+    pub fade_in: f32,
+    /// Seconds to stay fully shown between fading in and fading out.
+    pub hold: f32,
+    pub easing: Easing,
+    pub slide: Slide,
+    /// How far off its spot the toast starts and ends a slide, in pixels.
+    pub slide_distance: u32,
+    /// Size the toast grows from and shrinks back to, 1.0 for none of that.
+    pub scale_from: f32,
+}
+impl Default for Animation {
+    fn default() -> Self {
+        Animation { fade_in: 0.25, hold: 0.0, easing: Easing::default(), slide: Slide::default(), slide_distance: 40, scale_from: 1.0 }
+    }
+}
+
+/// What a toast looks like at one moment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub opacity: u8,
+    /// From where the toast would sit if it weren't moving.
+    pub offset: (i32, i32),
+    /// Of its full size, around its center.
+    pub scale: f32,
+    /// Seconds into the timeline, for anything on the toast that moves by itself.
+    pub time: f32,
+}
+
+/// The whole life of one toast.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeline {
+    pub animation: Animation,
+    pub fade_out: f32,
+    /// Opacity once fully faded in.
+    pub opacity: u8,
+}
+
+impl Timeline {
+    /// Seconds from showing up to being gone.
+    pub fn duration(&self) -> f32 {
+        self.animation.fade_in + self.animation.hold + self.fade_out
+    }
+
+    /// The frame for `t` seconds in, or `None` once it's over.
+    pub fn at(&self, t: f32) -> Option<Frame> {
+        let animation = &self.animation;
+        if t >= self.duration() {
+            return None;
+        }
+        // How much of the toast is showing: 0 before fading in and after fading out, 1 in between.
+        let shown = if t < animation.fade_in {
+            animation.easing.ease(t / animation.fade_in)
+        } else if t < animation.fade_in + animation.hold {
+            1.0
+        } else {
+            1.0 - animation.easing.ease((t - animation.fade_in - animation.hold) / self.fade_out)
+        };
+        let away = (1.0 - shown) * animation.slide_distance as f32;
+        let (dx, dy) = animation.slide.direction();
+        Some(Frame {
+            // Easings like `Back` overshoot, which only makes sense for movement.
+            opacity: (self.opacity as f32 * shown.clamp(0.0, 1.0)).round() as u8,
+            offset: ((dx * away).round() as i32, (dy * away).round() as i32),
+            scale: animation.scale_from + (1.0 - animation.scale_from) * shown,
+            time: t,
+        })
+    }
+
+    /// Plays the timeline out in real time, handing every frame to `on_frame` until it's over or `on_frame` returns false.
+    /// Slow frames don't slow the animation down, they're just skipped past.
+    pub fn play(&self, mut on_frame: impl FnMut(Frame) -> bool) {
+        let start = Instant::now();
+        while let Some(frame) = self.at(start.elapsed().as_secs_f32()) {
+            if !on_frame(frame) {
+                return;
+            }
+            let elapsed = start.elapsed();
+            let next = FRAME * (elapsed.as_nanos() / FRAME.as_nanos() + 1) as u32;
+            thread::sleep(next - elapsed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn easings_start_and_end_in_place() {
+        for easing in [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut, Easing::Back] {
+            assert!(easing.ease(0.0).abs() < 1e-6, "{:?}", easing);
+            assert!((easing.ease(1.0) - 1.0).abs() < 1e-6, "{:?}", easing);
+        }
+        assert!(Easing::EaseIn.ease(0.5) < 0.5);
+        assert!(Easing::EaseOut.ease(0.5) > 0.5);
+        assert_eq!(Easing::EaseInOut.ease(0.5), 0.5);
+        assert!(Easing::Back.ease(0.8) > 1.0);
+    }
+
+    #[test]
+    fn fades_in_holds_and_fades_out() {
+        let animation = Animation { fade_in: 1.0, hold: 2.0, easing: Easing::Linear, ..Animation::default() };
+        let timeline = Timeline { animation, fade_out: 4.0, opacity: 200 };
+        let opacity = |t| timeline.at(t).map(|frame| frame.opacity);
+        assert_eq!(timeline.duration(), 7.0);
+        assert_eq!(opacity(0.0), Some(0));
+        assert_eq!(opacity(0.5), Some(100));
+        assert_eq!(opacity(2.0), Some(200));
+        assert_eq!(opacity(5.0), Some(100));
+        assert_eq!(opacity(7.0), None);
+    }
+
+    #[test]
+    fn slides_and_scales() {
+        let animation = Animation {
+            fade_in: 1.0,
+            easing: Easing::Linear,
+            slide: Slide::Right,
+            slide_distance: 40,
+            scale_from: 0.5,
+            ..Animation::default()
+        };
+        let timeline = Timeline { animation, fade_out: 1.0, opacity: 255 };
+        let start = timeline.at(0.0).unwrap();
+        assert_eq!((start.offset, start.scale), ((40, 0), 0.5));
+        let halfway = timeline.at(0.5).unwrap();
+        assert_eq!((halfway.offset, halfway.scale), ((20, 0), 0.75));
+        let shown = timeline.at(1.0).unwrap();
+        assert_eq!((shown.offset, shown.scale, shown.opacity), ((0, 0), 1.0, 255));
+        // Back out the way it came.
+        assert_eq!(timeline.at(1.75).unwrap().offset, (30, 0));
+    }
+
+    #[test]
+    fn overshoot_only_moves() {
+        let animation = Animation { fade_in: 1.0, easing: Easing::Back, slide: Slide::Up, ..Animation::default() };
+        let frame = Timeline { animation, fade_out: 1.0, opacity: 200 }.at(0.8).unwrap();
+        assert_eq!(frame.opacity, 200);
+        assert!(frame.offset.1 > 0);
+    }
+
+    #[test]
+    fn plays_in_real_time() {
+        let animation = Animation { fade_in: 0.0, ..Animation::default() };
+        let timeline = Timeline { animation, fade_out: 0.1, opacity: 255 };
+        let start = Instant::now();
+        let mut frames = 0;
+        timeline.play(|_| {
+            frames += 1;
+            true
+        });
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!((2..=8).contains(&frames), "{} frames", frames);
+        let mut frames = 0;
+        timeline.play(|_| {
+            frames += 1;
+            false
+        });
+        assert_eq!(frames, 1);
+    }
+}
@@ -0,0 +1,328 @@
+use crate::{config::{self, Config}, media::*, props::*, render::*, rules::*, watcher::*};
+#[cfg(any(windows, target_os = "linux"))]
+use crate::{placement::{self, *}, toasts::*};
+#[cfg(any(windows, target_os = "linux"))]
+use std::sync::{LazyLock, Mutex};
+#[cfg(windows)]
+use crate::config::MonitorPick;
+#[cfg(target_os = "linux")]
+use crate::{config::Output, notify::Notifier, overlay::*};
+use clap::{Parser, Subcommand};
+#[allow(unused_imports)]
+use std::{fs, io::{Error, ErrorKind}, path::{Path, PathBuf}, result::Result, thread, time::Duration};
+
+#[derive(Parser)]
+#[command(name = "song_spectre", version, about = "Toasts whatever your media players are playing")]
+pub struct Cli {
+    /// Config file to use instead of the one in the user's config dir.
+    #[arg(short, long, global = true)]
+    pub config: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// List the media sessions and the app each one belongs to.
+    List,
+    /// Print what's playing right now.
+    Now {
+        /// Only this session, instead of all of them.
+        /// With `--json` that prints just its props, which is what `preview` takes.
+        #[arg(short, long)]
+        session: Option<String>,
+        /// Print JSON instead of text.
+        #[arg(long)]
+        json: bool,
+        /// How to put the thumbnail in the JSON: `png`, `hash`, `omit` or `file:<dir>`.
+        #[arg(long, default_value = "hash")]
+        thumb: ThumbEncoding,
+    },
+    /// Keep running and toast every new track. This is what happens with no command.
+    Watch {
+        /// Play back a scripted timeline instead of watching the real players.
+        #[arg(long)]
+        script: Option<PathBuf>,
+    },
+    /// Render the toast for a props JSON file (like `now --json` prints) to a PNG, with the config's rules applied.
+    Preview {
+        props: PathBuf,
+        #[arg(short, long, default_value = "toast.png")]
+        output: PathBuf,
+    },
+    /// Save the current thumbnail of a session.
+    Art {
+        /// Defaults to the current session, or the first one if none is current.
+        #[arg(short, long)]
+        session: Option<String>,
+        #[arg(short, long, default_value = "art.png")]
+        output: PathBuf,
+        /// Cut it into a `square`, `circle` or `vinyl`.
+        #[arg(long, default_value = "square")]
+        shape: ArtShape,
+        /// Round the corners of a square off to this radius.
+        #[arg(long, default_value_t = 0)]
+        radius: u32,
+        /// Give it a drop shadow spreading this far around it.
+        #[arg(long, default_value_t = 0)]
+        shadow: u32,
+    },
+}
+
+pub fn run(cli: Cli) -> Result<(), Error> {
+    let command = cli.command.unwrap_or(Command::Watch { script: None });
+    if let Some(path) = cli.config.or_else(Config::default_path) {
+        // Only something that keeps running needs to see edits.
+        match command {
+            Command::Watch { .. } => config::watch(path, Duration::from_secs(1))?,
+            _ => config::set(Config::load(&path)?),
+        }
+    }
+    match command {
+        Command::List => list(),
+        Command::Now { session, json, thumb } => now(session, json, &thumb),
+        Command::Watch { script } => watch(script),
+        Command::Preview { props, output } => preview(&props, &output),
+        Command::Art { session, output, shape, radius, shadow } => art(session, &output, shape, radius, shadow),
+    }
+}
+
+/// The given session, or every session if there isn't one.
+fn pick_sessions(source: &dyn MediaSource, session: Option<String>) -> Result<Vec<String>, Error> {
+    match session {
+        Some(session) => Ok(vec![session]),
+        None => source.sessions(),
+    }
+}
+
+fn list() -> Result<(), Error> {
+    let source = default_source()?;
+    for session in source.sessions()? {
+        match source.props(&session) {
+            Ok(props) => println!("{}\t{}\t{} - {}", session, props.source.name, props.title, props.artist),
+            Err(e) => println!("{}\t({})", session, e),
+        }
+    }
+    Ok(())
+}
+
+fn now(session: Option<String>, json: bool, thumb: &ThumbEncoding) -> Result<(), Error> {
+    let source = default_source()?;
+    if let (Some(session), true) = (&session, json) {
+        println!("{}", source.props(session)?.to_json(thumb)?);
+        return Ok(());
+    }
+    let sessions = pick_sessions(&*source, session)?;
+    if json {
+        let mut all = serde_json::Map::new();
+        for session in sessions {
+            let props = source.props(&session)?;
+            all.insert(session, serde_json::to_value(props.with_thumbs(thumb)).map_err(Error::other)?);
+        }
+        println!("{}", serde_json::to_string_pretty(&all).map_err(Error::other)?);
+    } else {
+        for session in sessions {
+            println!("[{}]", session);
+            println!("{}", source.props(&session)?);
+        }
+    }
+    Ok(())
+}
+
+fn watch(script: Option<PathBuf>) -> Result<(), Error> {
+    let source: Box<dyn MediaSource> = match script {
+        Some(script) => Box::new(ScriptedSource::load(&script)?),
+        None => default_source()?,
+    };
+    let mut watcher = Watcher::new(source);
+    // Only connected to once notifications are asked for.
+    #[cfg(target_os = "linux")]
+    let mut notifier: Option<Notifier> = None;
+    println!("\n-----------------start-----------------");
+    watcher.run(|track| {
+        println!("[{}]", track.session);
+        println!("{}", track.props);
+        #[cfg(target_os = "linux")]
+        if config::get().toast.output == Output::Notification {
+            return notify(&mut notifier, &track);
+        }
+        let title = track.props.title.clone();
+        toast_thread(track.session, title, track.props, track.settings);
+
+        //#[cfg(debug_assertions)]
+        //let _  = crate::utils::debug::display_spec_props(&spec_props);
+    })?;
+    println!("------------------end------------------");
+    Ok(())
+}
+
+fn preview(props: &Path, output: &Path) -> Result<(), Error> {
+    let props = SpectreProps::from_json(&fs::read_to_string(props)?)?;
+    let config = config::get();
+    let settings = Verdict::of(&config.rules, &props.source.id, &props).settings(&config, &props);
+    render_toast(&props, &settings.layout).save(output).map_err(Error::other)?;
+    println!("{}", output.display());
+    Ok(())
+}
+
+fn art(session: Option<String>, output: &Path, shape: ArtShape, radius: u32, shadow: u32) -> Result<(), Error> {
+    let source = default_source()?;
+    let session = match session {
+        Some(session) => session,
+        // The one media keys would control, which is the one that's meant with more than one player open.
+        None => match source.current_session()? {
+            Some(session) => session,
+            None => source.sessions()?.into_iter().next()
+                .ok_or(Error::new(ErrorKind::NotFound, "Nothing is playing"))?,
+        },
+    };
+    let mut art = source.props(&session)?.thumbnail.shaped(shape, radius);
+    if shadow > 0 {
+        art = art.drop_shadow(shadow, config::get().theme.shadow_color);
+    }
+    art.save(output).map_err(Error::other)?;
+    println!("{}", output.display());
+    Ok(())
+}
+
+/// Sends `track` as a desktop notification, falling back on a toast if there's no notification server to send it to.
+#[cfg(target_os = "linux")]
+fn notify(notifier: &mut Option<Notifier>, track: &NewTrack) {
+    if notifier.is_none() {
+        *notifier = Notifier::new().map_err(|e| println!("Couldn't connect for notifications: {}", e)).ok();
+    }
+    let sent = notifier.as_mut().map(|notifier| notifier.notify(&track.session, &track.props, &track.settings));
+    match sent {
+        Some(Ok(_)) => {}
+        Some(Err(e)) => println!("Couldn't send notification: {}", e),
+        None => toast_thread(track.session.clone(), track.props.title.clone(), track.props.clone(), track.settings.clone()),
+    }
+}
+
+/// Every toast on screen or waiting to be. Each toast tells it which monitor it's going on as it's queued.
+#[cfg(any(windows, target_os = "linux"))]
+static TOASTS: LazyLock<Mutex<ToastManager>> = LazyLock::new(|| Mutex::new(ToastManager::new(Monitor::single((0, 0)))));
+
+/// Puts a toast for `session` in line on `monitor`, with `settings` scaled to it.
+#[cfg(any(windows, target_os = "linux"))]
+fn queue_toast(session: &str, monitor: Monitor, settings: ToastSettings) -> (ToastId, ToastSettings) {
+    let config = config::get();
+    let settings = settings.scaled(placement::scale(&config.position, &monitor));
+    let mut toasts = TOASTS.lock().unwrap();
+    toasts.configure(&config);
+    toasts.set_monitor(monitor);
+    (toasts.push(session, (settings.layout.width, settings.layout.height)), settings)
+}
+
+/// Waits for toast `id` to get a spot on screen. `None` if it got replaced before it did.
+#[cfg(any(windows, target_os = "linux"))]
+fn wait_for_spot(id: ToastId) -> Option<(i32, i32)> {
+    loop {
+        match TOASTS.lock().unwrap().slot(id) {
+            Slot::Shown(origin) => return Some(origin),
+            Slot::Queued => {}
+            Slot::Gone => return None,
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Follows a toast around as the stack shifts. Jumping straight to the new spot looks jarring, so it eases over.
+#[cfg(any(windows, target_os = "linux"))]
+struct Follow {
+    id: ToastId,
+    at: (f32, f32),
+}
+#[cfg(any(windows, target_os = "linux"))]
+impl Follow {
+    fn new(id: ToastId, origin: (i32, i32)) -> Self {
+        Follow { id, at: (origin.0 as f32, origin.1 as f32) }
+    }
+
+    /// Where the toast goes this frame, `None` once it's been replaced.
+    fn next(&mut self) -> Option<(i32, i32)> {
+        let Slot::Shown(to) = TOASTS.lock().unwrap().slot(self.id) else { return None };
+        self.at.0 += (to.0 as f32 - self.at.0) * 0.25;
+        self.at.1 += (to.1 as f32 - self.at.1) * 0.25;
+        Some((self.at.0.round() as i32, self.at.1.round() as i32))
+    }
+}
+
+/// Shows a toast once the `ToastManager` has a spot for it, following it around the stack until it's faded or replaced.
+#[cfg(windows)]
+fn toast_thread(session: String, title: String, props: SpectreProps, settings: ToastSettings) {
+    use crate::ghoast::{cursor_pos, dpi_aware, monitors, player_window, screen_size};
+    dpi_aware();
+    let config = config::get();
+    let hints = Hints {
+        cursor: cursor_pos(),
+        // Hunting the player's window down means going through every window there is.
+        player: (config.position.monitor == MonitorPick::Player).then(|| player_window(&props.source.id)).flatten(),
+    };
+    let monitor = pick_monitor(&monitors(), config.position.monitor, &hints)
+        .unwrap_or(Monitor::single(screen_size()));
+    let (id, settings) = queue_toast(&session, monitor, settings);
+    thread::spawn(move || {
+        let Some(origin) = wait_for_spot(id) else { return };
+        let timeline = settings.timeline();
+        let mut marquee = Marquee::new(&props, &settings.layout);
+        let mut t = crate::utils::debug::show_ghoast(&title, props, settings, origin);
+        let mut follow = Follow::new(id, origin);
+        t.animate(&timeline, |t, frame| {
+            if let Some(marquee) = &mut marquee {
+                t.set_image(marquee.at(frame.time));
+            }
+            follow.next().is_some_and(|at| t.apply(frame, at))
+        });
+        TOASTS.lock().unwrap().remove(id);
+    });
+}
+/// Same as on windows, on Wayland or X11. With neither around the frame just gets saved, see `save_toast`.
+#[cfg(target_os = "linux")]
+fn toast_thread(session: String, _title: String, props: SpectreProps, settings: ToastSettings) {
+    let display = match Display::connect() {
+        Ok(display) => display,
+        Err(e) => {
+            println!("Nothing to show the toast on ({}), saving it instead", e);
+            return save_toast(&props, &settings);
+        }
+    };
+    let config = config::get();
+    // There's no telling which window is the player's from here.
+    let hints = Hints { cursor: display.cursor(), player: None };
+    let Some(monitor) = pick_monitor(&display.monitors(), config.position.monitor, &hints) else { return };
+    let (id, settings) = queue_toast(&session, monitor, settings);
+    thread::spawn(move || {
+        let Some(origin) = wait_for_spot(id) else { return };
+        match display.toast(render_toast(&props, &settings.layout), &monitor, &config.position, origin) {
+            Ok(mut toast) => {
+                let mut marquee = Marquee::new(&props, &settings.layout);
+                let mut follow = Follow::new(id, origin);
+                settings.timeline().play(|frame| {
+                    if let Some(marquee) = &mut marquee {
+                        toast.set_image(marquee.at(frame.time));
+                    }
+                    follow.next().is_some_and(|at| toast.apply(frame, at).is_ok())
+                });
+            }
+            Err(e) => println!("Couldn't show toast: {}", e),
+        }
+        TOASTS.lock().unwrap().remove(id);
+    });
+}
+// No toast window on other platforms yet.
+#[cfg(not(any(windows, target_os = "linux")))]
+fn toast_thread(_session: String, _title: String, props: SpectreProps, settings: ToastSettings) {
+    save_toast(&props, &settings);
+}
+
+/// Dumps the toast frame to the temp dir to look at, for when there's nothing to show it on.
+#[cfg_attr(windows, allow(dead_code))]
+fn save_toast(props: &SpectreProps, settings: &ToastSettings) {
+    let frame = render_toast(props, &settings.layout);
+    let path = std::env::temp_dir().join("Spectre-toast.png");
+    match frame.save(&path) {
+        Ok(_) => println!("Toast: {}", path.display()),
+        Err(e) => println!("Couldn't save toast: {}", e),
+    }
+}
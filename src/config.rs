@@ -0,0 +1,553 @@
+//! The config file, and keeping the running copy of it up to date.
+//!
+//! Lives at `song_spectre/config.toml` in the user's config dir (`~/.config` on Linux, `%APPDATA%` on Windows).
+//! Everything in it is optional, anything left out keeps its default:
+//! ```toml
+//! [toast]
+//! theme = "card"     # start from a theme, see `themes`, with the rest of this file on top
+//! fade_out = 5.0     # seconds the toast takes to fade away
+//! opacity = 126      # how opaque the toast starts out, 0-255
+//! show_source = true # name and icon of the app above the title
+//! max = 3            # toasts on screen at once, the rest wait their turn
+//! spacing = 8        # gap between stacked toasts
+//! output = "overlay" # or "notification" for the desktop's own notifications (Linux only)
+//!
+//! [size]
+//! width = 480
+//! height = 160
+//! padding = 16
+//! art = 128          # album art as drawn on the toast
+//! art_radius = 0     # rounded corners on the art
+//! art_shadow = 0     # how far the art's drop shadow spreads, taken from the art's space
+//! thumb = 300        # thumbnails as they're kept in SpectreProps
+//! progress = 4       # height of the progress bar, 0 for none
+//! radius = 0         # rounded corners
+//! shadow = 0         # how far the drop shadow spreads, taken from the edges of the toast
+//!
+//! [cache]
+//! memory = 64        # thumbnails kept in memory, 0 for none
+//! disk = false       # also keep them in the user's cache dir, across runs
+//!
+//! [position]
+//! anchor = "bottom-right"
+//! margin = [24, 24]
+//! monitor = "primary" # or "cursor", or "player" for the one with the player's window
+//! dpi_scale = true    # scale toasts and margins to the monitor's DPI
+//!
+//! [animation]
+//! fade_in = 0.25
+//! hold = 0.0          # seconds fully shown before fading out
+//! easing = "ease-out" # "linear", "ease-in", "ease-out", "ease-in-out" or "back"
+//! slide = "none"      # side to slide in from and out to: "left", "right", "up", "down"
+//! slide_distance = 40
+//! scale_from = 1.0    # e.g. 0.8 to grow in and shrink out
+//!
+//! [theme]
+//! background = "#7e7e7e"
+//! title_color = "#ffffff"
+//! text_color = "#e6e6e6"
+//! title_size = 24.0
+//! text_size = 16.0
+//! line_spacing = 2
+//! progress_color = "#ffffff"
+//! source_size = 13.0
+//! tint = "none"      # or "dominant", "vibrant" or "muted" to color the toast after the album art
+//! style = "solid"    # or "blurred" to fill the toast with the album art, blurred
+//! blur = 12.0        # how blurred, for "blurred"
+//! dim = 0.45         # how much darker, 0-1, for "blurred"
+//! overflow = "ellipsis" # what a title too long for the toast does: "clip", "ellipsis" or "marquee" to scroll it
+//! title_font = "bold"   # "regular", "bold" or the path of a font file
+//! text_font = "regular"
+//! shadow_color = "#000000a0"
+//! art_shape = "square" # or "circle", or "vinyl" for a circle with a hole in the middle
+//!
+//! [elements]         # put things where you want them instead, see `render::Elements`. Left out means not shown
+//! art = { x = 0, y = 0, width = 160, height = 160 }
+//! title = { x = 176, y = 40, width = 288, align = "center", size = 20.0, color = "#ffcc00", font = "regular" }
+//! progress = { x = 176, y = 120, width = 288, height = 4 }
+//!
+//! [focus]
+//! policy = "os"      # which session gets toasts: "os", "recent", "pinned" or "all"
+//! pin = "spotify"    # the app for "pinned", matched like ignore_apps
+//!
+//! [filters]
+//! ignore_sessions = ["firefox"]   # matched case-insensitively against the session id
+//! ignore_apps = ["chrome"]        # matched case-insensitively against the app's id and name
+//! ignore_types = ["video"]
+//!
+//! [[rule]]           # any number of these, see `rules`
+//! app = "chrome"
+//! action = "deny"
+//! ```
+use crate::anim::Animation;
+use crate::props::*;
+use crate::render::{text::{FontChoice, Overflow}, BackgroundStyle, Elements, Layout};
+use crate::rules::Rule;
+use crate::themes;
+use image::Rgba;
+use serde::{de::Error as _, Deserialize, Deserializer};
+use std::{fs, io::{Error, ErrorKind}, path::{Path, PathBuf}, result::Result,
+    sync::{Arc, LazyLock, RwLock}, thread, time::{Duration, SystemTime}};
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub toast: Toast,
+    pub size: Size,
+    pub cache: Cache,
+    pub position: Position,
+    pub animation: Animation,
+    pub theme: Theme,
+    pub focus: Focus,
+    pub filters: Filters,
+    pub elements: Option<Elements>,
+    #[serde(rename = "rule")]
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Toast {
+    /// Seconds the toast takes to fade out.
+    pub fade_out: f32,
+    /// Opacity the toast starts at.
+    pub opacity: u8,
+    pub show_source: bool,
+    /// Most toasts on screen at once.
+    pub max: usize,
+    /// Pixels between stacked toasts.
+    pub spacing: u32,
+    pub output: Output,
+    /// The built in theme or theme file the rest of the config goes on top of, see `themes`.
+    pub theme: Option<String>,
+}
+impl Default for Toast {
+    fn default() -> Self {
+        Toast { fade_out: 5.0, opacity: 126, show_source: Layout::default().show_source, max: 3, spacing: 8,
+            output: Output::default(), theme: None }
+    }
+}
+
+/// What tracks get shown with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Output {
+    /// Song Spectre's own toasts.
+    #[default]
+    Overlay,
+    /// A desktop notification through `org.freedesktop.Notifications`, see `notify`. Overlay everywhere but Linux.
+    Notification,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+    pub padding: u32,
+    pub art: u32,
+    /// Width and height thumbnails get fitted to when they come in, see `fit_thumb`.
+    pub thumb: u32,
+    pub progress: u32,
+    pub radius: u32,
+    pub shadow: u32,
+    pub art_radius: u32,
+    pub art_shadow: u32,
+}
+impl Default for Size {
+    fn default() -> Self {
+        let layout = Layout::default();
+        Size { width: layout.width, height: layout.height, padding: layout.padding, art: layout.art_size, thumb: THUMB_W,
+            progress: layout.progress_height, radius: layout.radius, shadow: layout.shadow, art_radius: layout.art_radius,
+            art_shadow: layout.art_shadow }
+    }
+}
+
+/// How thumbnails are cached, see `props::cache`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cache {
+    /// Most thumbnails kept in memory.
+    pub memory: usize,
+    /// Whether thumbnails also get saved to `Cache::dir`.
+    pub disk: bool,
+}
+impl Default for Cache {
+    fn default() -> Self {
+        Cache { memory: 64, disk: false }
+    }
+}
+impl Cache {
+    /// Where thumbnails go on disk, if there's a cache dir at all.
+    pub fn dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("song_spectre").join("thumbs"))
+    }
+}
+
+/// Which corner or edge of the screen the toast sits against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    BottomLeft,
+    Bottom,
+    #[default]
+    BottomRight,
+}
+
+/// Which monitor toasts go on, see `placement::pick_monitor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MonitorPick {
+    #[default]
+    Primary,
+    /// The one the mouse cursor is on.
+    Cursor,
+    /// The one most of the player's window is on.
+    Player,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Position {
+    pub anchor: Anchor,
+    /// Gap between the toast and the screen edges it's anchored to, in pixels.
+    pub margin: (u32, u32),
+    pub monitor: MonitorPick,
+    pub dpi_scale: bool,
+}
+impl Default for Position {
+    fn default() -> Self {
+        Position { anchor: Anchor::default(), margin: (24, 24), monitor: MonitorPick::default(), dpi_scale: true }
+    }
+}
+impl Position {
+    /// Top left corner of a `size` toast on a `screen` sized screen.
+    pub fn origin(&self, screen: (u32, u32), size: (u32, u32)) -> (i32, i32) {
+        let (mx, my) = (self.margin.0 as i32, self.margin.1 as i32);
+        let left = mx;
+        let center = (screen.0 as i32 - size.0 as i32) / 2;
+        let right = screen.0 as i32 - size.0 as i32 - mx;
+        let top = my;
+        let bottom = screen.1 as i32 - size.1 as i32 - my;
+        match self.anchor {
+            Anchor::TopLeft => (left, top),
+            Anchor::Top => (center, top),
+            Anchor::TopRight => (right, top),
+            Anchor::BottomLeft => (left, bottom),
+            Anchor::Bottom => (center, bottom),
+            Anchor::BottomRight => (right, bottom),
+        }
+    }
+
+    /// Whether toasts stack upwards from the anchor, rather than downwards.
+    pub fn stacks_up(&self) -> bool {
+        matches!(self.anchor, Anchor::BottomLeft | Anchor::Bottom | Anchor::BottomRight)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    #[serde(deserialize_with = "hex_color")]
+    pub background: Rgba<u8>,
+    #[serde(deserialize_with = "hex_color")]
+    pub title_color: Rgba<u8>,
+    #[serde(deserialize_with = "hex_color")]
+    pub text_color: Rgba<u8>,
+    pub title_size: f32,
+    pub text_size: f32,
+    pub line_spacing: u32,
+    #[serde(deserialize_with = "hex_color")]
+    pub progress_color: Rgba<u8>,
+    pub source_size: f32,
+    /// Swaps the background for one of the art's colors, and any text that doesn't read on it for black or white.
+    pub tint: Tint,
+    pub style: BackgroundStyle,
+    pub blur: f32,
+    pub dim: f32,
+    pub overflow: Overflow,
+    pub title_font: FontChoice,
+    pub text_font: FontChoice,
+    #[serde(deserialize_with = "hex_color")]
+    pub shadow_color: Rgba<u8>,
+    pub art_shape: ArtShape,
+}
+impl Default for Theme {
+    fn default() -> Self {
+        let layout = Layout::default();
+        Theme {
+            background: layout.background,
+            title_color: layout.title_color,
+            text_color: layout.text_color,
+            title_size: layout.title_size,
+            text_size: layout.text_size,
+            line_spacing: layout.line_spacing,
+            progress_color: layout.progress_color,
+            source_size: layout.source_size,
+            tint: Tint::default(),
+            style: layout.background_style,
+            blur: layout.blur,
+            dim: layout.dim,
+            overflow: layout.overflow,
+            title_font: layout.title_font,
+            text_font: layout.text_font,
+            shadow_color: layout.shadow_color,
+            art_shape: layout.art_shape,
+        }
+    }
+}
+
+/// Parses `#rrggbb` or `#rrggbbaa`.
+pub fn parse_color(s: &str) -> Option<Rgba<u8>> {
+    let hex = s.strip_prefix('#')?;
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i * 2..i * 2 + 2).unwrap_or("ff"), 16).ok();
+    Some(Rgba([channel(0)?, channel(1)?, channel(2)?, channel(3)?]))
+}
+
+fn hex_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rgba<u8>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_color(&s).ok_or_else(|| D::Error::custom(format!("Bad color: {} (expected #rrggbb or #rrggbbaa)", s)))
+}
+/// `hex_color` for colors that can be left out.
+pub(crate) fn opt_hex_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Rgba<u8>>, D::Error> {
+    hex_color(deserializer).map(Some)
+}
+
+/// How the session that gets toasts is picked when there's more than one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FocusPolicy {
+    /// Whatever the source says is current, see `MediaSource::current_session`.
+    #[default]
+    Os,
+    /// Whichever session most recently started playing or changed track.
+    Recent,
+    /// The `pin` app whenever it has a session, otherwise like `Os`.
+    Pinned,
+    /// Every session, focused or not.
+    All,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Focus {
+    pub policy: FocusPolicy,
+    pub pin: Option<String>,
+}
+
+/// Tracks that shouldn't get a toast.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Filters {
+    /// Sessions whose id contains any of these, ignoring case.
+    pub ignore_sessions: Vec<String>,
+    /// Apps whose id or name contains any of these, ignoring case. See `SourceApp::matches`.
+    pub ignore_apps: Vec<String>,
+    pub ignore_types: Vec<SPT>,
+}
+impl Filters {
+    pub fn allows(&self, session: &str, props: &SpectreProps) -> bool {
+        let session = session.to_lowercase();
+        !self.ignore_sessions.iter().any(|ignored| session.contains(&ignored.to_lowercase()))
+            && !self.ignore_apps.iter().any(|ignored| props.source.matches(ignored))
+            && !self.ignore_types.contains(&props.playback_type)
+    }
+}
+
+impl Config {
+    /// Where the config file is looked for when one isn't given.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("song_spectre").join("config.toml"))
+    }
+
+    /// Reads the config at `path`. A file that isn't there is just the defaults.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Parses a config, putting it on top of its `[toast] theme` if it has one.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let invalid = |e: toml::de::Error| Error::new(ErrorKind::InvalidData, e);
+        let table: toml::Table = toml::from_str(text).map_err(invalid)?;
+        let Some(theme) = table.get("toast").and_then(|toast| toast.get("theme")).and_then(|theme| theme.as_str()) else {
+            // Straight from the text, so mistakes get pointed out with where they are.
+            return toml::from_str(text).map_err(invalid);
+        };
+        let merged = themes::apply(themes::load(theme)?, table);
+        toml::Value::Table(merged).try_into().map_err(invalid)
+    }
+
+    /// The toast `Layout` these settings describe.
+    pub fn layout(&self) -> Layout {
+        Layout {
+            width: self.size.width,
+            height: self.size.height,
+            radius: self.size.radius,
+            shadow: self.size.shadow,
+            shadow_color: self.theme.shadow_color,
+            padding: self.size.padding,
+            art_size: self.size.art,
+            art_shape: self.theme.art_shape,
+            art_radius: self.size.art_radius,
+            art_shadow: self.size.art_shadow,
+            background: self.theme.background,
+            background_style: self.theme.style,
+            blur: self.theme.blur,
+            dim: self.theme.dim,
+            title_color: self.theme.title_color,
+            text_color: self.theme.text_color,
+            title_size: self.theme.title_size,
+            text_size: self.theme.text_size,
+            title_font: self.theme.title_font.clone(),
+            text_font: self.theme.text_font.clone(),
+            overflow: self.theme.overflow,
+            line_spacing: self.theme.line_spacing,
+            progress_height: self.size.progress,
+            progress_color: self.theme.progress_color,
+            show_source: self.toast.show_source,
+            source_size: self.theme.source_size,
+            elements: self.elements.clone(),
+        }
+    }
+}
+
+static CURRENT: LazyLock<RwLock<Arc<Config>>> = LazyLock::new(|| RwLock::new(Arc::new(Config::default())));
+
+/// The config as it is right now. Hang on to it for as long as one toast or one event needs it,
+/// then get it again so reloads get picked up.
+pub fn get() -> Arc<Config> {
+    CURRENT.read().unwrap().clone()
+}
+
+pub fn set(config: Config) {
+    *CURRENT.write().unwrap() = Arc::new(config);
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Notices when the config file changes, by its modification time.
+pub struct Reloader {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+impl Reloader {
+    /// Starts from the file as it is now, so only changes after this count.
+    pub fn new(path: PathBuf) -> Self {
+        let modified = modified(&path);
+        Reloader { path, modified }
+    }
+
+    /// # Returns
+    /// The reloaded config if the file changed (or appeared, or went away) since the last call, otherwise `None`.
+    pub fn poll(&mut self) -> Option<Result<Config, Error>> {
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(Config::load(&self.path))
+    }
+}
+
+/// Loads the config at `path` and keeps reloading it in the background whenever it changes.
+///
+/// A broken config at startup is an error, but a broken edit later only gets complained about and the last good one is kept.
+pub fn watch(path: PathBuf, every: Duration) -> Result<(), Error> {
+    let mut reloader = Reloader::new(path.clone());
+    set(Config::load(&path).map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?);
+    thread::spawn(move || loop {
+        thread::sleep(every);
+        match reloader.poll() {
+            Some(Ok(config)) => {
+                println!("Reloaded {}", path.display());
+                set(config);
+            }
+            Some(Err(e)) => println!("Couldn't reload {}: {}", path.display(), e),
+            None => {}
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn partial_config() {
+        let config = Config::parse(r##"
+            [toast]
+            fade_out = 2.5
+            [theme]
+            background = "#10203080"
+            [filters]
+            ignore_types = ["video"]
+        "##).unwrap();
+        assert_eq!(config.toast, Toast { fade_out: 2.5, ..Toast::default() });
+        assert_eq!(config.layout().background, Rgba([0x10, 0x20, 0x30, 0x80]));
+        assert_eq!(config.layout().title_color, Layout::default().title_color);
+        assert_eq!(config.size, Size::default());
+        assert_eq!(Config::parse("").unwrap().layout(), Layout::default());
+
+        assert!(Config::parse("[theme]\nbackground = \"grey\"").is_err());
+        assert!(Config::parse("[toast]\nfade = 1.0").is_err());
+    }
+
+    #[test]
+    fn filters() {
+        let filters = Filters {
+            ignore_sessions: vec!["Firefox".to_string()],
+            ignore_apps: vec!["chrome".to_string()],
+            ignore_types: vec![SPT::VIDEO],
+        };
+        let song = SpectreProps { playback_type: SPT::AUDIO, ..SpectreProps::new() };
+        let video = SpectreProps { playback_type: SPT::VIDEO, ..SpectreProps::new() };
+        assert!(filters.allows("spotify", &song));
+        assert!(!filters.allows("spotify", &video));
+        assert!(!filters.allows("org.mpris.MediaPlayer2.firefox.instance_1_2", &song));
+        let chrome = SpectreProps { source: SourceApp::from_id("Chrome"), ..song.clone() };
+        assert!(!filters.allows("308046B0AF4A39CB", &chrome));
+    }
+
+    #[test]
+    fn anchors() {
+        let mut position = Position::default();
+        assert_eq!(position.origin((1920, 1080), (480, 160)), (1920 - 480 - 24, 1080 - 160 - 24));
+        position.anchor = Anchor::Top;
+        assert_eq!(position.origin((1920, 1080), (480, 160)), (720, 24));
+    }
+
+    #[test]
+    fn reloads_on_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let mut reloader = Reloader::new(path.clone());
+        assert!(reloader.poll().is_none());
+
+        fs::write(&path, "[toast]\nopacity = 200").unwrap();
+        assert_eq!(reloader.poll().unwrap().unwrap().toast.opacity, 200);
+        assert!(reloader.poll().is_none());
+
+        // Filesystems can have coarse timestamps, so make sure the edit doesn't look like the same moment.
+        fs::write(&path, "[toast]\nopacity = \"lots\"").unwrap();
+        File::options().write(true).open(&path).unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(5)).unwrap();
+        assert!(reloader.poll().unwrap().is_err());
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(reloader.poll().unwrap().unwrap(), Config::default());
+    }
+}
@@ -1,18 +1,26 @@
-use image::{DynamicImage, GenericImageView};
+use image::{imageops, RgbaImage};
 use windows::{core::{w, PCWSTR}, 
     Win32::{Foundation::{self as WFound, COLORREF, HINSTANCE, HWND, LPARAM, LRESULT, WPARAM},
         Graphics::Gdi::{
-            self, InvalidateRect, RedrawWindow, UpdateWindow, HBRUSH, HRGN}, 
+            self, HBRUSH}, 
         System::LibraryLoader::GetModuleHandleA, 
         UI::WindowsAndMessaging::{self as WandM, 
-            CreateWindowExW, DestroyWindow, DispatchMessageW, GetLayeredWindowAttributes, GetMessageW, GetWindowLongPtrW, PostQuitMessage, RegisterClassW, SendMessageW, SetLayeredWindowAttributes, SetWindowLongPtrW, ShowWindow, TranslateMessage, HCURSOR, MSG, WNDCLASSW}}};
+            CreateWindowExW, DestroyWindow, DispatchMessageW, GetMessageW, PostQuitMessage, RegisterClassW, SendMessageW, ShowWindow, TranslateMessage, HCURSOR, MSG, WNDCLASSW}}};
 //I do not know why this glob import is necessary. but without it the window behaves incorrectly despite the compiler being happy.
 use windows::Win32::UI::WindowsAndMessaging::*;
+use crate::anim::*;
+use crate::placement::{Monitor, Rect};
 use crate::props::*;
+use crate::render::*;
+use windows::core::PWSTR;
+use windows::Win32::System::Threading::{OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION};
+use windows::Win32::UI::HiDpi::{GetDpiForMonitor, SetProcessDpiAwarenessContext, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2, MDT_EFFECTIVE_DPI};
+use crate::rules::ToastSettings;
 use crate::utils::*;
-use std::sync::{Arc, Once};
+use std::sync::Once;
 
-static mut TOAST_INSTANCE: Option<Arc<GhoastClass>> = None;
+// Registered once and never unregistered, so it lives for as long as the program does.
+static mut TOAST_INSTANCE: Option<&'static GhoastClass> = None;
 static INITIALIZE_ONCE: Once = Once::new(); // Once to ensure Toast is initialized only once
 
 unsafe extern "system" fn custom_window_proc(
@@ -22,67 +30,8 @@ unsafe extern "system" fn custom_window_proc(
     lparam: LPARAM,
 ) -> LRESULT {
     match msg {
-        WM_PAINT => {
-            let hdc = Gdi::GetDC(hwnd);
-            if hdc.is_invalid() {
-                println!("Failed to get device context.");
-                Gdi::DeleteDC(hdc);
-                return LRESULT(0);
-            } 
-            // Create a memory device context
-            let mem_dc = Gdi::CreateCompatibleDC(hdc);
-            if mem_dc.0.is_null() {
-                println!("Failed to create memory device context.");
-                Gdi::DeleteDC(hdc);
-                return LRESULT(0);
-            }
-            let thumbnail_ptr = unsafe { GetWindowLongPtrW(hwnd, WandM::GWLP_USERDATA) as *const DynamicImage };
-            if !thumbnail_ptr.is_null() {
-                let thumb = unsafe { &*thumbnail_ptr };
-            
-            //debug::view_image(Some(&var), "Ghoast");
-            let bitmap = match dynamic_image_to_bitmap(hdc, thumb) {
-                Ok(bmp) => bmp, // If successful, assign to bitmap
-                Err(e) => {
-                    println!("{}", e);
-                    Gdi::DeleteDC(mem_dc);
-                    Gdi::DeleteDC(hdc);
-                    return LRESULT(0); // Return early on error
-                }
-            };
-
-            // Select the bitmap into the device context
-            Gdi::SelectObject(mem_dc, bitmap);
-
-            // Use the dimensions of the image for the BitBlt
-            let (width, height) = thumb.dimensions();
-            // Draw the bitmap on the window
-            let blit_result = Gdi::BitBlt(
-                hdc,             // Destination device context
-                0, 0, // Destination coordinates
-                width as i32, height as i32, // Width and height of the bitmap
-                mem_dc,            // Source device context
-                0, 0,          // Source coordinates (from the bitmap)
-                Gdi::SRCCOPY,
-            );
-            if blit_result.is_err() {
-                println!("Failed to draw bitmap.");
-                println!("{:?}", blit_result)
-            } else {
-                println!("Bitmap drawn successfully.");
-            }
-
-            Gdi::DeleteObject(bitmap); // Delete the bitmap object
-            Gdi::DeleteDC(mem_dc); // Delete the memory DC
-            Gdi::DeleteDC(hdc);
-            return LRESULT(0);
-        } else {
-            print!("thumbnail ptr null");
-            return LRESULT(1);
-        }
-    }
         WM_CLOSE => {
-            DestroyWindow(hwnd); // Destroy the window
+            let _ = DestroyWindow(hwnd); // Destroy the window
             println!("CLOSE");
             LRESULT(0) // Indicate the message was handled
         }
@@ -97,15 +46,130 @@ unsafe extern "system" fn custom_window_proc(
 }
 
 
-pub fn make_color_ref(r: u8, g: u8, b: u8) -> COLORREF {
-    // Combine the RGB components into a COLORREF value
-    let color = (r as u32) | ((g as u32) << 8) | ((b as u32) << 16);
-    COLORREF(color)
+/// Size of the primary screen.
+pub fn screen_size() -> (u32, u32) {
+    unsafe { (GetSystemMetrics(SM_CXSCREEN) as u32, GetSystemMetrics(SM_CYSCREEN) as u32) }
+}
+
+fn to_rect(rect: WFound::RECT) -> Rect {
+    Rect { x: rect.left, y: rect.top, width: (rect.right - rect.left).max(0) as u32, height: (rect.bottom - rect.top).max(0) as u32 }
+}
+
+/// Has Windows hand us real pixels and DPIs instead of pretending every monitor is 96 DPI. Only the first call does anything.
+pub fn dpi_aware() {
+    static DPI_AWARE: Once = Once::new();
+    DPI_AWARE.call_once(|| {
+        let _ = unsafe { SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2) };
+    });
+}
+
+/// Every monitor, with its work area and DPI scale.
+pub fn monitors() -> Vec<Monitor> {
+    unsafe extern "system" fn add(hmonitor: Gdi::HMONITOR, _: Gdi::HDC, _: *mut WFound::RECT, data: LPARAM) -> WFound::BOOL {
+        let monitors = &mut *(data.0 as *mut Vec<Monitor>);
+        let mut info = Gdi::MONITORINFO { cbSize: std::mem::size_of::<Gdi::MONITORINFO>() as u32, ..Default::default() };
+        if Gdi::GetMonitorInfoW(hmonitor, &mut info).as_bool() {
+            let (mut dpi, mut dpi_y) = (96, 96);
+            let _ = GetDpiForMonitor(hmonitor, MDT_EFFECTIVE_DPI, &mut dpi, &mut dpi_y);
+            monitors.push(Monitor { work: to_rect(info.rcWork), scale: dpi as f32 / 96.0, primary: info.dwFlags & MONITORINFOF_PRIMARY != 0 });
+        }
+        WFound::TRUE
+    }
+    let mut monitors: Vec<Monitor> = Vec::new();
+    unsafe { let _ = Gdi::EnumDisplayMonitors(Gdi::HDC::default(), None, Some(add), LPARAM(&mut monitors as *mut _ as isize)); }
+    monitors
+}
+
+pub fn cursor_pos() -> Option<(i32, i32)> {
+    let mut point = WFound::POINT::default();
+    unsafe { GetCursorPos(&mut point) }.ok().map(|_| (point.x, point.y))
+}
+
+/// Lowercased file name, without `.exe`, of the program that owns `hwnd`.
+fn window_exe(hwnd: HWND) -> Option<String> {
+    let mut pid = 0;
+    unsafe { GetWindowThreadProcessId(hwnd, Some(&mut pid)) };
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) }.ok()?;
+    let mut path = [0u16; 1024];
+    let mut len = path.len() as u32;
+    let named = unsafe { QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR(path.as_mut_ptr()), &mut len) };
+    let _ = unsafe { WFound::CloseHandle(process) };
+    named.ok()?;
+    let path = String::from_utf16_lossy(&path[..len as usize]).to_lowercase();
+    let name = path.rsplit('\\').next().unwrap_or(&path);
+    Some(name.strip_suffix(".exe").unwrap_or(name).to_string())
+}
+
+/// Where the topmost visible window of the app with id `app_id` is, matched by the name of its exe.
+/// Packaged apps whose id doesn't mention their exe won't be found.
+pub fn player_window(app_id: &str) -> Option<Rect> {
+    struct Search { app_id: String, found: Option<Rect> }
+    unsafe extern "system" fn check(hwnd: HWND, data: LPARAM) -> WFound::BOOL {
+        let search = &mut *(data.0 as *mut Search);
+        let mut rect = WFound::RECT::default();
+        if IsWindowVisible(hwnd).as_bool() && GetWindowRect(hwnd, &mut rect).is_ok() && rect.right > rect.left
+            && window_exe(hwnd).is_some_and(|exe| !exe.is_empty() && search.app_id.contains(&exe)) {
+            search.found = Some(to_rect(rect));
+            return WFound::FALSE;
+        }
+        WFound::TRUE
+    }
+    let mut search = Search { app_id: app_id.to_lowercase(), found: None };
+    // Stopping early shows up as an error.
+    let _ = unsafe { EnumWindows(Some(check), LPARAM(&mut search as *mut _ as isize)) };
+    search.found
+}
+
+
+/// Puts `frame` on screen with its top left corner at `at`, through `UpdateLayeredWindow`. Each pixel keeps its own
+/// alpha, so rounded corners and shadows are see-through, with `opacity` on top of that for the whole window.
+fn present(hwnd: HWND, frame: &RgbaImage, at: (i32, i32), opacity: u8) -> windows::core::Result<()> {
+    let (width, height) = frame.dimensions();
+    let info = Gdi::BITMAPINFO {
+        bmiHeader: Gdi::BITMAPINFOHEADER {
+            biSize: std::mem::size_of::<Gdi::BITMAPINFOHEADER>() as u32,
+            biWidth: width as i32,
+            biHeight: -(height as i32), // Negative for top-down rows, like the image's
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: Gdi::BI_RGB.0,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    unsafe {
+        let screen = Gdi::GetDC(HWND::default());
+        let mem_dc = Gdi::CreateCompatibleDC(screen);
+        let mut bits = std::ptr::null_mut();
+        let result = Gdi::CreateDIBSection(mem_dc, &info, Gdi::DIB_RGB_COLORS, &mut bits, None, 0).and_then(|bitmap| {
+            // Layered windows want their pixels as premultiplied BGRA.
+            let pixels = std::slice::from_raw_parts_mut(bits as *mut u8, (width * height * 4) as usize);
+            for (dst, pixel) in pixels.chunks_exact_mut(4).zip(frame.pixels()) {
+                let [r, g, b, a] = premultiply(pixel.0, 255);
+                dst.copy_from_slice(&[b, g, r, a]);
+            }
+            let old = Gdi::SelectObject(mem_dc, bitmap);
+            let blend = Gdi::BLENDFUNCTION {
+                BlendOp: Gdi::AC_SRC_OVER as u8,
+                BlendFlags: 0,
+                SourceConstantAlpha: opacity,
+                AlphaFormat: Gdi::AC_SRC_ALPHA as u8,
+            };
+            let result = UpdateLayeredWindow(hwnd, screen, Some(&WFound::POINT { x: at.0, y: at.1 }),
+                Some(&WFound::SIZE { cx: width as i32, cy: height as i32 }), mem_dc, Some(&WFound::POINT::default()),
+                COLORREF(0), Some(&blend), ULW_ALPHA);
+            Gdi::SelectObject(mem_dc, old);
+            let _ = Gdi::DeleteObject(bitmap);
+            result
+        });
+        let _ = Gdi::DeleteDC(mem_dc);
+        Gdi::ReleaseDC(HWND::default(), screen);
+        result
+    }
 }
 #[derive(Debug)]
 pub struct GhoastClass {
     pub class: WNDCLASSW,
-    pub atom: u16,
     pub h_instance: HINSTANCE,
 }
 impl GhoastClass {
@@ -130,13 +194,13 @@ impl GhoastClass {
             slp(1.0);
             return None;
         }
-        Some(Self { class, atom, h_instance})
+        Some(Self { class, h_instance})
     }
-    pub fn instance() -> Arc<GhoastClass> { unsafe {
+    pub fn instance() -> &'static GhoastClass { unsafe {
             INITIALIZE_ONCE.call_once(|| {
-                TOAST_INSTANCE = Some(Arc::new(GhoastClass::new().unwrap()));
+                TOAST_INSTANCE = Some(Box::leak(Box::new(GhoastClass::new().unwrap())));
             });
-            TOAST_INSTANCE.clone().expect("Failed to get Toast instance")
+            (*std::ptr::addr_of!(TOAST_INSTANCE)).expect("Failed to get Toast instance")
         }
     }
 }
@@ -147,12 +211,19 @@ pub struct Ghoast {
     pub c_name: String,
     pub is_good: bool,
     pub title: String,
-    pub props: SpectreProps,
+    pub settings: ToastSettings,
+    /// Where the window sits when it's not animating.
+    pub origin: (i32, i32),
+    /// The rendered toast, at full size.
+    frame: RgbaImage,
 }
 impl Ghoast {
-    pub fn new(title: &str, props: SpectreProps) -> Self {
+    /// A toast window with its top left corner at `origin`, see `ToastManager`.
+    pub fn new(title: &str, props: SpectreProps, settings: ToastSettings, origin: (i32, i32)) -> Self {
         let inst = GhoastClass::instance();
         let name = inst.class.lpszClassName;
+        let frame = render_toast(&props, &settings.layout);
+        let (x, y) = origin;
             // Create the window using the registered class
             let hwnd = unsafe {
                 CreateWindowExW(
@@ -161,29 +232,26 @@ impl Ghoast {
                     name,
                     PCWSTR::from_raw(title.encode_utf16().chain(Some(0)).collect::<Vec<u16>>().as_ptr()),
                     WandM::WS_POPUP,
-                    WandM::CW_USEDEFAULT, WandM::CW_USEDEFAULT,
-                    300, 300, 
+                    x, y,
+                    frame.width() as i32, frame.height() as i32, 
                     HWND::default(), // Parent window
                     None, // Menu
                     inst.h_instance, // Instance handle
                     None, // Additional data
                 )
             }.unwrap();
-        let thumbnail_ptr = Box::into_raw(Box::new(props.thumbnail.clone()));
-        unsafe { SetWindowLongPtrW(hwnd, WandM::GWLP_USERDATA, thumbnail_ptr as _) };
-        Self { hwnd , h_instance: inst.h_instance, c_name: unsafe { name.to_string().unwrap_or_default() }, is_good: true, title: title.to_string(), props}
+        Self { hwnd , h_instance: inst.h_instance, c_name: unsafe { name.to_string().unwrap_or_default() }, is_good: true, title: title.to_string(), settings, origin, frame}
     }    // Method to show the window
     pub fn init(&self) {
+            let _ = present(self.hwnd, &self.frame, self.origin, self.settings.opacity);
             self.show();
-            self.update();
-            self.set_transparency(make_color_ref(126, 126, 126), 126);
             self.check_messages();          
     }  
     fn check_messages(&self)->bool {
         unsafe {
             let mut msg: MSG = std::mem::zeroed();
             if  GetMessageW(&mut msg, HWND::default(), 0, 0).into() {
-                TranslateMessage(&msg);
+                let _ = TranslateMessage(&msg);
                 DispatchMessageW(&msg);
                 true
             } else {
@@ -191,73 +259,61 @@ impl Ghoast {
             }
         }
     }
-    pub fn message_loop(&mut self)->bool {
-        let boo = self.check_messages();
-        if !boo { self.is_good = false;}
-        boo
+    /// Plays `timeline` out and closes the window, handing every frame to `on_frame` to put on screen with `apply`.
+    /// The toast closes early if it returns false.
+    pub fn animate(&mut self, timeline: &Timeline, mut on_frame: impl FnMut(&mut Self, Frame) -> bool) {
+        timeline.play(|frame| self.pump_messages() && on_frame(self, frame));
+        self.destruct();
     }
-    pub fn fade_out(&mut self, seconds: f32) -> bool {
-        let cref = make_color_ref(126, 126, 126);
-        let mut alpha = self.get_current_alpha().unwrap();
-        let dur = (seconds/alpha as f32);
-        while self.message_loop() {
-            alpha -= 1;
-            println!("{}", alpha);
-            if alpha < 1 {
-                self.destruct();
-                break;
-            } else {
-            let _ = self.set_transparency(cref, alpha);
-            self.redraw();
-            slp(dur);
-        }}
-        return false;
+    /// Shows `frame`, with the toast's resting spot at `origin`.
+    pub fn apply(&mut self, frame: Frame, origin: (i32, i32)) -> bool {
+        self.origin = origin;
+        let (width, height) = (self.settings.layout.width as f32, self.settings.layout.height as f32);
+        let (scaled_width, scaled_height) = (width * frame.scale, height * frame.scale);
+        let x = origin.0 + frame.offset.0 + ((width - scaled_width) / 2.0).round() as i32;
+        let y = origin.1 + frame.offset.1 + ((height - scaled_height) / 2.0).round() as i32;
+        // Scaled here rather than stretched by GDI, which would lose the alpha.
+        let (scaled_width, scaled_height) = (scaled_width.round().max(1.0) as u32, scaled_height.round().max(1.0) as u32);
+        let result = if (scaled_width, scaled_height) == self.frame.dimensions() {
+            present(self.hwnd, &self.frame, (x, y), frame.opacity)
+        } else {
+            let scaled = imageops::resize(&self.frame, scaled_width, scaled_height, imageops::FilterType::Triangle);
+            present(self.hwnd, &scaled, (x, y), frame.opacity)
+        };
+        result.is_ok()
+    }
+    /// Swaps what the toast shows for `image`, the same size, from the next `apply` on.
+    pub fn set_image(&mut self, image: RgbaImage) {
+        self.frame = image;
+    }
+    /// Handles whatever messages are waiting without blocking. False once the window's gone.
+    fn pump_messages(&mut self) -> bool {
+        unsafe {
+            let mut msg = MSG::default();
+            while PeekMessageW(&mut msg, HWND::default(), 0, 0, PM_REMOVE).as_bool() {
+                if msg.message == WM_QUIT {
+                    self.is_good = false;
+                    break;
+                }
+                let _ = TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+        }
+        self.is_good
     }
 
     pub fn destruct(&mut self) {
         // Send the WM_CLOSE message to the window
         self.message_self(WandM::WM_CLOSE);
-        while self.check_messages() { 
-            println!("SUICIDE")
-        }
         self.is_good = false;
     }
 
     fn show(&self) -> bool{
         unsafe {ShowWindow(self.hwnd, WandM::SW_SHOW)}.into()
     }
-    fn update(&self) -> bool{
-        unsafe {UpdateWindow(self.hwnd)}.into()
-    }
-    fn set_transparency(&self, crkey: COLORREF, alpha: u8) -> Result<(), windows::core::Error> {
-        unsafe {SetLayeredWindowAttributes(self.hwnd, crkey, alpha, WandM::LWA_ALPHA)}
-    }
-    pub fn redraw(&self) -> bool{
-        unsafe { RedrawWindow(self.hwnd, None, HRGN::default(), Gdi::RDW_INVALIDATE | Gdi::RDW_ALLCHILDREN) }.into()
-    }
     pub fn message_self(&self, msg: u32) -> LRESULT {
         unsafe {SendMessageW(self.hwnd, msg, WPARAM(0), LPARAM(0))}
     }
-    pub fn request_paint(&self) {
-        unsafe {
-            // Invalidate the window's client area
-            InvalidateRect(self.hwnd, None, WFound::TRUE);
-
-            // Update the window to send a WM_PAINT message immediately
-            UpdateWindow(self.hwnd);
-        }
-    }
-    pub fn get_current_alpha(&self) -> Option<u8> {
-        let mut alpha: u8 = 0;
-        let mut crkey: COLORREF = COLORREF(0);
-    
-        // Call GetLayeredWindowAttributes to retrieve current attributes
-        if unsafe { GetLayeredWindowAttributes(self.hwnd, None, Some(&mut alpha), None).is_ok() } {
-            Some(alpha)
-        } else {
-            None
-        }
-    }
 }
 
 
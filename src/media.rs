@@ -0,0 +1,71 @@
+#[cfg(windows)]
+pub mod tcs;
+#[cfg(windows)]
+pub use tcs::TcsSource;
+#[cfg(target_os = "linux")]
+pub mod mpris;
+#[cfg(target_os = "linux")]
+pub use mpris::MprisSource;
+pub mod script;
+pub use script::ScriptedSource;
+
+use crate::props::SpectreProps;
+#[allow(unused_imports)]
+use std::{io::{Error, ErrorKind}, result::Result, sync::mpsc::Sender};
+
+/// Something that changed in a `MediaSource`. Events only say *what* changed,
+/// the listener is expected to go back to the source for the new state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MediaEvent {
+    /// A new session showed up.
+    SessionAdded(String),
+    /// The session went away.
+    SessionRemoved(String),
+    /// The metadata (title, artist, thumbnail...) of the session changed.
+    PropsChanged(String),
+    /// The session started playing, paused, stopped etc.
+    PlaybackChanged(String),
+}
+
+/// A provider of now-playing sessions, e.g. the Windows TCS session manager.
+///
+/// Sessions are identified by a string id that is stable for as long as the session lives.
+pub trait MediaSource {
+    /// Lists the ids of every session the source currently knows about.
+    fn sessions(&self) -> Result<Vec<String>, Error>;
+    /// Gets the media properties for the session with the provided id.
+    fn props(&self, session: &str) -> Result<SpectreProps, Error>;
+    /// The session the OS considers current, the one media keys would control.
+    /// Sources without such a thing give the session that most recently started playing. `None` if nothing is.
+    fn current_session(&self) -> Result<Option<String>, Error>;
+    /// Starts sending `MediaEvent`s to `tx` whenever something changes, starting with a `SessionAdded` for every
+    /// session that already exists. Events keep coming for as long as the receiving end is alive.
+    fn subscribe(&self, tx: Sender<MediaEvent>) -> Result<(), Error>;
+}
+
+impl<S: MediaSource + ?Sized> MediaSource for Box<S> {
+    fn sessions(&self) -> Result<Vec<String>, Error> { (**self).sessions() }
+    fn props(&self, session: &str) -> Result<SpectreProps, Error> { (**self).props(session) }
+    fn current_session(&self) -> Result<Option<String>, Error> { (**self).current_session() }
+    fn subscribe(&self, tx: Sender<MediaEvent>) -> Result<(), Error> { (**self).subscribe(tx) }
+}
+impl<S: MediaSource + ?Sized> MediaSource for &S {
+    fn sessions(&self) -> Result<Vec<String>, Error> { (**self).sessions() }
+    fn props(&self, session: &str) -> Result<SpectreProps, Error> { (**self).props(session) }
+    fn current_session(&self) -> Result<Option<String>, Error> { (**self).current_session() }
+    fn subscribe(&self, tx: Sender<MediaEvent>) -> Result<(), Error> { (**self).subscribe(tx) }
+}
+
+/// Gets the `MediaSource` for the platform we're running on.
+#[cfg(windows)]
+pub fn default_source() -> Result<Box<dyn MediaSource>, Error> {
+    Ok(Box::new(TcsSource::new()?))
+}
+#[cfg(target_os = "linux")]
+pub fn default_source() -> Result<Box<dyn MediaSource>, Error> {
+    Ok(Box::new(MprisSource::new()?))
+}
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn default_source() -> Result<Box<dyn MediaSource>, Error> {
+    Err(Error::new(ErrorKind::Unsupported, "No media backend for this platform"))
+}
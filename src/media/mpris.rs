@@ -0,0 +1,499 @@
+use super::{MediaEvent, MediaSource};
+use crate::props::*;
+use std::{collections::HashMap, env, fs, io::Error, path::{Path, PathBuf}, result::Result, sync::{mpsc::Sender, Arc, Mutex}, thread, time::Duration};
+use zbus::{blocking::{fdo::DBusProxy, Connection, MessageIterator},
+    message::Type as MsgType, zvariant::{OwnedValue, Value}, MatchRule};
+
+const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const MPRIS_PATH: &str = "/org/mpris/MediaPlayer2";
+const ROOT_IFACE: &str = "org.mpris.MediaPlayer2";
+const PLAYER_IFACE: &str = "org.mpris.MediaPlayer2.Player";
+const PROPS_IFACE: &str = "org.freedesktop.DBus.Properties";
+
+trait ZbusToIoErrExt<T> { fn map_err_io(self) -> Result<T, Error>; }
+
+impl<T> ZbusToIoErrExt<T> for zbus::Result<T> {
+    fn map_err_io(self) -> Result<T, Error> {
+        self.map_err(|e| match e {
+            zbus::Error::InputOutput(io) => Error::new(io.kind(), io.to_string()),
+            e => Error::other(e),
+        })
+}}
+impl<T> ZbusToIoErrExt<T> for zbus::fdo::Result<T> {
+    fn map_err_io(self) -> Result<T, Error> {
+        self.map_err(Error::other)
+}}
+
+/// `MediaSource` for the MPRIS players (`org.mpris.MediaPlayer2.*`) on a D-Bus session bus.
+///
+/// Session ids are the players' well-known bus names, e.g. `org.mpris.MediaPlayer2.spotify`.
+pub struct MprisSource {
+    conn: Connection,
+    // Looking an app up means reading its desktop file and icon, so each one only gets done once.
+    apps: Arc<Mutex<HashMap<String, SourceApp>>>,
+    // The player that most recently started playing, MPRIS has no "current player" of its own.
+    last_playing: Arc<Mutex<Option<String>>>,
+}
+impl MprisSource {
+    /// Connects to the user's session bus.
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self::with_connection(Connection::session().map_err_io()?))
+    }
+    /// Uses an already open bus connection, for when the players live somewhere other than the session bus.
+    pub fn with_connection(conn: Connection) -> Self {
+        Self { conn, apps: Arc::new(Mutex::new(HashMap::new())), last_playing: Arc::new(Mutex::new(None)) }
+    }
+
+    /// Every property of `iface` of `session`, in one round trip.
+    fn all_props(&self, session: &str, iface: &str) -> Result<HashMap<String, OwnedValue>, Error> {
+        let reply = self.conn.call_method(Some(session), MPRIS_PATH, Some(PROPS_IFACE), "GetAll", &(iface,)).map_err_io()?;
+        reply.body().deserialize().map_err_io()
+    }
+
+    fn status(&self, session: &str) -> PlaybackStatus {
+        let reply = self.conn.call_method(Some(session), MPRIS_PATH, Some(PROPS_IFACE), "Get", &(PLAYER_IFACE, "PlaybackStatus"));
+        match reply.and_then(|reply| reply.body().deserialize::<OwnedValue>()) {
+            Ok(status) => match &*status {
+                Value::Str(s) => s.parse().unwrap_or_default(),
+                _ => PlaybackStatus::Unknown,
+            },
+            Err(_) => PlaybackStatus::Unknown,
+        }
+    }
+
+    /// Works out the app from the root interface's `Identity` and `DesktopEntry`, falling back on the bus name.
+    fn app(&self, session: &str) -> SourceApp {
+        if let Some(app) = self.apps.lock().unwrap().get(session) {
+            return app.clone();
+        }
+        let root = self.all_props(session, ROOT_IFACE).unwrap_or_default();
+        let root_str = |key: &str| match root.get(key).map(|v| &**v) {
+            Some(Value::Str(s)) if !s.is_empty() => Some(s.to_string()),
+            _ => None,
+        };
+        let desktop_entry = root_str("DesktopEntry");
+        let id = desktop_entry.clone().unwrap_or_else(|| bus_name_app(session).to_string());
+        let app = SourceApp {
+            name: root_str("Identity").unwrap_or_else(|| name_from_id(&id)),
+            icon: desktop_entry.and_then(|entry| desktop_icon(&entry, &data_dirs()))
+                .and_then(|path| image::open(path).ok()),
+            id,
+        };
+        self.apps.lock().unwrap().insert(session.to_string(), app.clone());
+        app
+    }
+}
+
+impl MediaSource for MprisSource {
+    fn sessions(&self) -> Result<Vec<String>, Error> {
+        let names = DBusProxy::new(&self.conn).map_err_io()?.list_names().map_err_io()?;
+        Ok(names.into_iter()
+            .map(|name| name.to_string())
+            .filter(|name| name.starts_with(MPRIS_PREFIX))
+            .collect())
+    }
+
+    fn props(&self, session: &str) -> Result<SpectreProps, Error> {
+        let mut player = self.all_props(session, PLAYER_IFACE)?;
+        let metadata: HashMap<String, OwnedValue> = match player.remove("Metadata") {
+            Some(metadata) => metadata.try_into().map_err(Error::other)?,
+            None => HashMap::new(),
+        };
+        let mut spectre_props = SpectreProps::from_mpris(&metadata);
+        spectre_props.sync_mpris_player(&player);
+        spectre_props.source = self.app(session);
+        Ok(spectre_props)
+    }
+
+    /// The player that most recently started playing if it still is, otherwise any player that's playing.
+    /// Only knows about "most recently" while subscribed.
+    fn current_session(&self) -> Result<Option<String>, Error> {
+        let playing = |session: &str| self.status(session) == PlaybackStatus::Playing;
+        let last = self.last_playing.lock().unwrap().clone();
+        if let Some(last) = last.filter(|last| playing(last)) {
+            return Ok(Some(last));
+        }
+        Ok(self.sessions()?.into_iter().find(|session| playing(session)))
+    }
+
+    fn subscribe(&self, tx: Sender<MediaEvent>) -> Result<(), Error> {
+        // Make the iterator before adding the match rules so nothing slips through in between.
+        let messages = MessageIterator::from(&self.conn);
+        let dbus = DBusProxy::new(&self.conn).map_err_io()?;
+        let owner_rule = MatchRule::builder()
+            .msg_type(MsgType::Signal)
+            .interface("org.freedesktop.DBus").map_err_io()?
+            .member("NameOwnerChanged").map_err_io()?
+            .arg0ns("org.mpris.MediaPlayer2").map_err_io()?
+            .build();
+        let props_rule = MatchRule::builder()
+            .msg_type(MsgType::Signal)
+            .interface(PROPS_IFACE).map_err_io()?
+            .member("PropertiesChanged").map_err_io()?
+            .path(MPRIS_PATH).map_err_io()?
+            .build();
+        dbus.add_match_rule(owner_rule).map_err_io()?;
+        dbus.add_match_rule(props_rule).map_err_io()?;
+
+        // PropertiesChanged comes from the player's unique name (":1.42"), so keep track of who owns what.
+        let mut owners: HashMap<String, String> = HashMap::new();
+        for name in self.sessions()? {
+            if let Ok(owner) = dbus.get_name_owner(name.as_str().try_into().map_err(Error::other)?) {
+                owners.insert(owner.to_string(), name.clone());
+            }
+            let _ = tx.send(MediaEvent::SessionAdded(name));
+        }
+
+        // A name changing hands could be a different app entirely.
+        let apps = self.apps.clone();
+        let last_playing = self.last_playing.clone();
+        thread::spawn(move || {
+            for msg in messages {
+                let Ok(msg) = msg else { continue };
+                let header = msg.header();
+                if header.message_type() != MsgType::Signal { continue; }
+                let event = match header.member().map(|m| m.as_str()) {
+                    Some("NameOwnerChanged") => {
+                        let Ok((name, old, new)) = msg.body().deserialize::<(String, String, String)>() else { continue };
+                        if !name.starts_with(MPRIS_PREFIX) { continue; }
+                        owners.remove(&old);
+                        apps.lock().unwrap().remove(&name);
+                        if new.is_empty() {
+                            let mut last = last_playing.lock().unwrap();
+                            if last.as_ref() == Some(&name) {
+                                *last = None;
+                            }
+                            MediaEvent::SessionRemoved(name)
+                        } else {
+                            owners.insert(new, name.clone());
+                            MediaEvent::SessionAdded(name)
+                        }
+                    }
+                    Some("PropertiesChanged") => {
+                        let Some(name) = header.sender().and_then(|s| owners.get(s.as_str())).cloned() else { continue };
+                        let Ok((iface, changed, _)) = msg.body().deserialize::<(String, HashMap<String, OwnedValue>, Vec<String>)>() else { continue };
+                        if iface != PLAYER_IFACE { continue; }
+                        if changed.get("PlaybackStatus").is_some_and(|s| matches!(&**s, Value::Str(s) if s.as_str() == "Playing")) {
+                            *last_playing.lock().unwrap() = Some(name.clone());
+                        }
+                        if changed.contains_key("Metadata") {
+                            MediaEvent::PropsChanged(name)
+                        } else if ["PlaybackStatus", "LoopStatus", "Shuffle"].iter().any(|key| changed.contains_key(*key)) {
+                            MediaEvent::PlaybackChanged(name)
+                        } else { continue }
+                    }
+                    _ => continue,
+                };
+                if tx.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+fn meta_str(metadata: &HashMap<String, OwnedValue>, key: &str) -> Option<String> {
+    match &**metadata.get(key)? {
+        Value::Str(s) if !s.is_empty() => Some(s.to_string()),
+        _ => None,
+    }
+}
+/// xesam list fields are `as`, but plenty of players send a plain string instead.
+fn meta_list(metadata: &HashMap<String, OwnedValue>, key: &str) -> Vec<String> {
+    match metadata.get(key).map(|v| &**v) {
+        Some(Value::Str(s)) => vec![s.to_string()],
+        Some(Value::Array(arr)) => arr.iter()
+            .filter_map(|v| match v { Value::Str(s) => Some(s.to_string()), _ => None })
+            .collect(),
+        _ => vec![],
+    }
+}
+fn meta_int(metadata: &HashMap<String, OwnedValue>, key: &str) -> Option<i32> {
+    match &**metadata.get(key)? {
+        Value::I32(n) => Some(*n),
+        Value::I64(n) => i32::try_from(*n).ok(),
+        Value::U32(n) => i32::try_from(*n).ok(),
+        Value::U64(n) => i32::try_from(*n).ok(),
+        _ => None,
+    }
+}
+
+/// The app part of an MPRIS bus name, `org.mpris.MediaPlayer2.firefox.instance_1_84` is `firefox`.
+fn bus_name_app(bus_name: &str) -> &str {
+    let name = bus_name.strip_prefix(MPRIS_PREFIX).unwrap_or(bus_name);
+    name.split(".instance").next().unwrap_or(name)
+}
+
+/// `$XDG_DATA_HOME` and `$XDG_DATA_DIRS`, the places desktop files and icons live.
+fn data_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = dirs::data_dir().into_iter().collect();
+    let system = env::var("XDG_DATA_DIRS").ok().filter(|d| !d.is_empty()).unwrap_or("/usr/local/share:/usr/share".to_string());
+    dirs.extend(env::split_paths(&system));
+    dirs
+}
+
+/// Finds the icon of the desktop entry `entry` (as in `entry.desktop`) as a PNG.
+///
+/// Only looks in the hicolor theme and pixmaps, which is where pretty much every app puts its icon anyway.
+fn desktop_icon(entry: &str, data_dirs: &[PathBuf]) -> Option<PathBuf> {
+    let file = format!("{}.desktop", entry);
+    let desktop = data_dirs.iter().find_map(|dir| fs::read_to_string(dir.join("applications").join(&file)).ok())?;
+    let icon = desktop.lines()
+        .skip_while(|line| line.trim() != "[Desktop Entry]")
+        .skip(1)
+        .take_while(|line| !line.starts_with('['))
+        .find_map(|line| line.strip_prefix("Icon="))?
+        .trim();
+    if Path::new(icon).is_absolute() {
+        return Path::new(icon).exists().then(|| PathBuf::from(icon));
+    }
+    let png = format!("{}.png", icon);
+    let sizes = ["256x256", "128x128", "96x96", "64x64", "48x48", "32x32"];
+    data_dirs.iter().find_map(|dir| {
+        sizes.iter()
+            .map(|size| dir.join("icons/hicolor").join(size).join("apps").join(&png))
+            .chain(Some(dir.join("pixmaps").join(&png)))
+            .find(|path| path.exists())
+    })
+}
+
+/// MPRIS times are in microseconds. They're supposed to be `x`, but not every player agrees.
+fn micros(value: &Value) -> Option<Duration> {
+    let micros = match value {
+        Value::I64(n) => u64::try_from(*n).ok()?,
+        Value::U64(n) => *n,
+        Value::I32(n) => u64::try_from(*n).ok()?,
+        Value::U32(n) => *n as u64,
+        _ => return None,
+    };
+    Some(Duration::from_micros(micros))
+}
+
+/// Decodes the `%XX` escapes in a URI path.
+fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(b) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    out
+}
+
+/// Loads `mpris:artUrl` into a thumbnail, through the thumbnail cache. Only local `file://` art is supported, anything else gets the `ERROR_THUMB`.
+pub fn art_url_to_thumb(url: Option<&str>) -> DynamicImage {
+    let Some(path) = url.and_then(|u| u.strip_prefix("file://")) else {
+        return ERROR_THUMB.clone();
+    };
+    let path = String::from_utf8_lossy(&percent_decode(path)).into_owned();
+    match fs::read(path) {
+        Ok(data) => thumb_from_bytes(&data),
+        Err(_) => ERROR_THUMB.clone(),
+    }
+}
+
+impl SpectreProps {
+    /// Creates a new `SpectreProps` from the `Metadata` property of an MPRIS player.
+    pub fn from_mpris(metadata: &HashMap<String, OwnedValue>) -> Self {
+        let mut spectre_props = Self::new();
+        spectre_props.sync_mpris(metadata);
+        spectre_props
+    }
+
+    pub fn sync_mpris(&mut self, metadata: &HashMap<String, OwnedValue>) {
+        let artists = meta_list(metadata, "xesam:artist");
+        let album_artists = meta_list(metadata, "xesam:albumArtist");
+        self.title = meta_str(metadata, "xesam:title").unwrap_or(UNKNOWN_TITLE.to_string());
+        self.artist = if artists.is_empty() { UNKNOWN_ARTIST.to_string() } else { artists.join(", ") };
+        self.album = meta_str(metadata, "xesam:album").unwrap_or(UNKNOWN_ALBUM.to_string());
+        self.album_artist = if album_artists.is_empty() { None } else { Some(album_artists.join(", ")) };
+        self.genres = meta_list(metadata, "xesam:genre");
+        self.thumbnail = art_url_to_thumb(meta_str(metadata, "mpris:artUrl").as_deref());
+        self.track_number = meta_int(metadata, "xesam:trackNumber");
+        self.duration = metadata.get("mpris:length").and_then(|v| micros(v)).filter(|d| !d.is_zero());
+        // MPRIS has no notion of either of these.
+        self.track_count = None;
+        self.playback_type = SPT::UNKNOWN;
+        self.subtitle = None;
+    }
+
+    /// Updates the playback state from the rest of the player's properties (everything but `Metadata`).
+    /// `LoopStatus` and `Shuffle` are optional in MPRIS, so they're `None` for players that don't have them.
+    pub fn sync_mpris_player(&mut self, player: &HashMap<String, OwnedValue>) {
+        let str_prop = |key: &str| match player.get(key).map(|v| &**v) {
+            Some(Value::Str(s)) => Some(s.to_string()),
+            _ => None,
+        };
+        self.status = str_prop("PlaybackStatus").and_then(|s| s.parse().ok()).unwrap_or_default();
+        self.position = player.get("Position").and_then(|v| micros(v));
+        self.repeat = str_prop("LoopStatus").and_then(|s| s.parse().ok());
+        self.shuffle = match player.get("Shuffle").map(|v| &**v) {
+            Some(Value::Bool(b)) => Some(*b),
+            _ => None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_bus::TestBus;
+    use std::{sync::mpsc, time::Duration};
+    use zbus::blocking::connection::Builder as ConnBuilder;
+
+    struct FakePlayer {
+        metadata: HashMap<String, OwnedValue>,
+    }
+    #[zbus::interface(name = "org.mpris.MediaPlayer2.Player")]
+    impl FakePlayer {
+        #[zbus(property)]
+        fn metadata(&self) -> HashMap<String, OwnedValue> {
+            self.metadata.iter().map(|(k, v)| (k.clone(), v.try_clone().unwrap())).collect()
+        }
+        #[zbus(property)]
+        fn playback_status(&self) -> String {
+            "Paused".to_string()
+        }
+        #[zbus(property)]
+        fn position(&self) -> i64 {
+            83_000_000
+        }
+        #[zbus(property)]
+        fn loop_status(&self) -> String {
+            "Playlist".to_string()
+        }
+    }
+
+    struct FakeRoot;
+    #[zbus::interface(name = "org.mpris.MediaPlayer2")]
+    impl FakeRoot {
+        #[zbus(property)]
+        fn identity(&self) -> String {
+            "Fake Player".to_string()
+        }
+        #[zbus(property)]
+        fn desktop_entry(&self) -> String {
+            "org.example.fake".to_string()
+        }
+    }
+
+    fn owned<'a>(v: impl Into<Value<'a>>) -> OwnedValue {
+        v.into().try_into_owned().unwrap()
+    }
+
+    fn fake_player(bus: &TestBus, name: &str, metadata: HashMap<String, OwnedValue>) -> Connection {
+        ConnBuilder::address(bus.address.as_str()).unwrap()
+            .name(format!("{}{}", MPRIS_PREFIX, name)).unwrap()
+            .serve_at(MPRIS_PATH, FakePlayer { metadata }).unwrap()
+            .serve_at(MPRIS_PATH, FakeRoot).unwrap()
+            .build().unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon, run with --ignored"]
+    fn maps_player_metadata() {
+        let bus = TestBus::start();
+        let art_dir = tempfile::tempdir().unwrap();
+        let art_path = art_dir.path().join("cover art.png");
+        DynamicImage::new_rgb8(64, 32).save(&art_path).unwrap();
+
+        let metadata = HashMap::from([
+            ("xesam:title".to_string(), owned("Ghost Town")),
+            ("xesam:artist".to_string(), owned(vec!["Kanye West", "PARTYNEXTDOOR"])),
+            ("xesam:album".to_string(), owned("ye")),
+            ("xesam:albumArtist".to_string(), owned(vec!["Kanye West"])),
+            ("xesam:genre".to_string(), owned(vec!["Hip Hop"])),
+            ("xesam:trackNumber".to_string(), owned(6i32)),
+            ("mpris:length".to_string(), owned(271_000_000i64)),
+            ("mpris:artUrl".to_string(), owned(format!("file://{}", art_path.display()).replace(' ', "%20"))),
+        ]);
+        let _player = fake_player(&bus, "fake", metadata);
+        let source = MprisSource::with_connection(bus.connect());
+
+        assert_eq!(source.sessions().unwrap(), vec!["org.mpris.MediaPlayer2.fake".to_string()]);
+        let props = source.props("org.mpris.MediaPlayer2.fake").unwrap();
+        assert_eq!(props.title, "Ghost Town");
+        assert_eq!(props.artist, "Kanye West, PARTYNEXTDOOR");
+        assert_eq!(props.album, "ye");
+        assert_eq!(props.album_artist.as_deref(), Some("Kanye West"));
+        assert_eq!(props.genres, vec!["Hip Hop".to_string()]);
+        assert_eq!(props.track_number, Some(6));
+        assert_eq!(props.status, PlaybackStatus::Paused);
+        assert_eq!(props.position, Some(Duration::from_secs(83)));
+        assert_eq!(props.duration, Some(Duration::from_secs(271)));
+        assert_eq!(props.repeat, Some(RepeatMode::List));
+        // The fake player doesn't have Shuffle at all.
+        assert_eq!(props.shuffle, None);
+        assert_eq!((props.source.id.as_str(), props.source.name.as_str()), ("org.example.fake", "Fake Player"));
+        assert_eq!((props.thumbnail.width(), props.thumbnail.height()), (THUMB_W, THUMB_H));
+        assert!(props.thumbnail != *ERROR_THUMB);
+    }
+
+    #[test]
+    fn finds_desktop_icons() {
+        let data = tempfile::tempdir().unwrap();
+        let dirs = [data.path().join("empty"), data.path().to_path_buf()];
+        fs::create_dir_all(data.path().join("applications")).unwrap();
+        fs::write(data.path().join("applications/player.desktop"),
+            "[Desktop Entry]\nName=Player\nIcon=player-icon\n\n[Desktop Action New]\nIcon=wrong\n").unwrap();
+        assert_eq!(desktop_icon("player", &dirs), None);
+
+        let icon = data.path().join("icons/hicolor/48x48/apps/player-icon.png");
+        fs::create_dir_all(icon.parent().unwrap()).unwrap();
+        DynamicImage::new_rgb8(48, 48).save(&icon).unwrap();
+        assert_eq!(desktop_icon("player", &dirs), Some(icon));
+        assert_eq!(desktop_icon("nothing", &dirs), None);
+
+        assert_eq!(bus_name_app("org.mpris.MediaPlayer2.firefox.instance_1_84"), "firefox");
+        assert_eq!(bus_name_app("org.mpris.MediaPlayer2.spotify"), "spotify");
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon, run with --ignored"]
+    fn missing_metadata_falls_back() {
+        let bus = TestBus::start();
+        let _player = fake_player(&bus, "empty", HashMap::new());
+        let source = MprisSource::with_connection(bus.connect());
+
+        let props = source.props("org.mpris.MediaPlayer2.empty").unwrap();
+        assert_eq!(props.title, "Unknown Title");
+        assert_eq!(props.artist, "Unknown Artist");
+        assert_eq!(props.album_artist, None);
+        assert_eq!(props.duration, None);
+        assert!(props.thumbnail == *ERROR_THUMB);
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon, run with --ignored"]
+    fn sends_session_and_props_events() {
+        let bus = TestBus::start();
+        let first = fake_player(&bus, "first", HashMap::new());
+        let source = MprisSource::with_connection(bus.connect());
+        let (tx, rx) = mpsc::channel();
+        source.subscribe(tx).unwrap();
+        let next = || rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        assert_eq!(next(), MediaEvent::SessionAdded("org.mpris.MediaPlayer2.first".to_string()));
+
+        let second = fake_player(&bus, "second", HashMap::new());
+        assert_eq!(next(), MediaEvent::SessionAdded("org.mpris.MediaPlayer2.second".to_string()));
+
+        let changed = HashMap::from([("Metadata", owned(HashMap::<String, OwnedValue>::new()))]);
+        first.emit_signal(None::<()>, MPRIS_PATH, "org.freedesktop.DBus.Properties", "PropertiesChanged",
+            &(PLAYER_IFACE, changed, Vec::<String>::new())).unwrap();
+        assert_eq!(next(), MediaEvent::PropsChanged("org.mpris.MediaPlayer2.first".to_string()));
+
+        drop(second);
+        assert_eq!(next(), MediaEvent::SessionRemoved("org.mpris.MediaPlayer2.second".to_string()));
+    }
+}
@@ -0,0 +1,311 @@
+use super::{MediaEvent, MediaSource};
+use crate::props::*;
+use indexmap::IndexMap;
+use serde::Deserialize;
+use std::{fs, io::{Error, ErrorKind}, path::{Path, PathBuf}, result::Result, sync::{mpsc::Sender, Arc, Mutex}, thread, time::Duration};
+
+/// A timeline of fake session changes, loaded from a JSON or TOML file.
+///
+/// ```toml
+/// [[step]]
+/// session = "spotify"
+/// props = { title = "Ghost Town", artist = "Kanye West", thumbnail = "art/ye.png" }
+///
+/// [[step]]
+/// at = 2.5
+/// session = "spotify"
+/// ```
+///
+/// What a step does depends on what it has in it:
+/// * `removed = true` removes the session.
+/// * A session that doesn't exist yet gets added, with `props` or the `SpectreProps` defaults.
+/// * `props` on an existing session replaces its props.
+/// * No `props` on an existing session is just a playback change, which can set the session's `status`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Script {
+    #[serde(rename = "step", alias = "steps", default)]
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Step {
+    /// Seconds since the start of playback. Only matters when the script is played back in real time.
+    #[serde(default)]
+    pub at: f32,
+    pub session: String,
+    #[serde(default)]
+    pub props: Option<ScriptProps>,
+    #[serde(default)]
+    pub removed: bool,
+    /// New `PlaybackStatus` for the session, e.g. `"paused"`.
+    #[serde(default)]
+    pub status: Option<PlaybackStatus>,
+}
+
+/// The parts of `SpectreProps` a script can set. Anything left out gets the `SpectreProps` default.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ScriptProps {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub genres: Vec<String>,
+    /// Path to an image, relative to the script file.
+    pub thumbnail: Option<PathBuf>,
+    pub track_number: Option<i32>,
+    pub track_count: Option<i32>,
+    pub playback_type: Option<String>,
+    pub subtitle: Option<String>,
+    pub status: Option<PlaybackStatus>,
+    /// Seconds into the track.
+    pub position: Option<f32>,
+    /// Length of the track in seconds.
+    pub duration: Option<f32>,
+    pub shuffle: Option<bool>,
+    pub repeat: Option<RepeatMode>,
+    /// Name of the app playing. The session id is the app id, and the name is made from that if it's left out.
+    pub app: Option<String>,
+    /// Path to the app's icon, relative to the script file.
+    pub app_icon: Option<PathBuf>,
+}
+
+impl ScriptProps {
+    fn to_spectre_props(&self, session: &str, base_dir: &Path) -> SpectreProps {
+        let defaults = SpectreProps::new();
+        SpectreProps {
+            title: self.title.clone().unwrap_or(defaults.title),
+            artist: self.artist.clone().unwrap_or(defaults.artist),
+            album: self.album.clone().unwrap_or(defaults.album),
+            album_artist: self.album_artist.clone(),
+            genres: self.genres.clone(),
+            thumbnail: match &self.thumbnail {
+                Some(path) => match image::open(base_dir.join(path)) {
+                    Ok(img) => fit_thumb(img),
+                    Err(_) => ERROR_THUMB.clone(),
+                },
+                None => defaults.thumbnail,
+            },
+            track_number: self.track_number,
+            track_count: self.track_count,
+            playback_type: self.playback_type.as_deref().and_then(|t| t.parse().ok()).unwrap_or(defaults.playback_type),
+            subtitle: self.subtitle.clone(),
+            status: self.status.unwrap_or(defaults.status),
+            position: self.position.and_then(|p| Duration::try_from_secs_f32(p).ok()),
+            duration: self.duration.and_then(|d| Duration::try_from_secs_f32(d).ok()),
+            shuffle: self.shuffle,
+            repeat: self.repeat,
+            source: SourceApp {
+                name: self.app.clone().unwrap_or_else(|| name_from_id(session)),
+                icon: self.app_icon.as_ref().and_then(|path| image::open(base_dir.join(path)).ok()),
+                ..SourceApp::from_id(session)
+            },
+        }
+    }
+}
+
+impl Script {
+    /// Reads a script from a file, as TOML if it ends in `.toml` and as JSON otherwise.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = fs::read_to_string(path)?;
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("toml")) {
+            toml::from_str(&text).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+        } else {
+            serde_json::from_str(&text).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+        }
+    }
+}
+
+#[derive(Default)]
+struct ScriptState {
+    next: usize,
+    sessions: IndexMap<String, SpectreProps>,
+    /// The session that most recently started playing.
+    current: Option<String>,
+    subscribers: Vec<Sender<MediaEvent>>,
+    playing: bool,
+}
+
+/// `MediaSource` that plays back a `Script` instead of talking to real players.
+///
+/// Subscribing starts real-time playback of the script on its own thread, honoring the `at` of each step,
+/// and the subscribers are hung up once the script runs out. For lockstep tests, skip `subscribe`
+/// and drive it one step at a time with `advance`.
+pub struct ScriptedSource {
+    steps: Arc<Vec<Step>>,
+    base_dir: PathBuf,
+    state: Arc<Mutex<ScriptState>>,
+}
+
+impl ScriptedSource {
+    /// # Arguments
+    /// * `script` - The timeline to play back.
+    /// * `base_dir` - Where thumbnail paths in the script are relative to.
+    pub fn new(script: Script, base_dir: &Path) -> Self {
+        Self {
+            steps: Arc::new(script.steps),
+            base_dir: base_dir.to_path_buf(),
+            state: Arc::new(Mutex::new(ScriptState::default())),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let script = Script::load(path)?;
+        Ok(Self::new(script, path.parent().unwrap_or(Path::new("."))))
+    }
+
+    /// Applies the next step of the script and sends its event to every subscriber.
+    ///
+    /// # Returns
+    /// The event the step caused, or `None` once the script is over.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn advance(&self) -> Option<MediaEvent> {
+        apply_next(&self.steps, &self.base_dir, &self.state)
+    }
+}
+
+fn apply_next(steps: &[Step], base_dir: &Path, state: &Mutex<ScriptState>) -> Option<MediaEvent> {
+    let mut state = state.lock().unwrap();
+    let step = steps.get(state.next)?;
+    state.next += 1;
+    let session = step.session.clone();
+    let was_playing = state.sessions.get(&session).is_some_and(|props| props.status == PlaybackStatus::Playing);
+    let props = step.props.as_ref().map(|p| p.to_spectre_props(&session, base_dir));
+    let event = if step.removed {
+        state.sessions.shift_remove(&session);
+        MediaEvent::SessionRemoved(session)
+    } else if !state.sessions.contains_key(&session) {
+        let props = props.unwrap_or_else(|| ScriptProps::default().to_spectre_props(&session, base_dir));
+        state.sessions.insert(session.clone(), props);
+        MediaEvent::SessionAdded(session)
+    } else if let Some(props) = props {
+        state.sessions.insert(session.clone(), props);
+        MediaEvent::PropsChanged(session)
+    } else {
+        if let (Some(status), Some(props)) = (step.status, state.sessions.get_mut(&session)) {
+            props.status = status;
+        }
+        MediaEvent::PlaybackChanged(session)
+    };
+    // Only an explicit `playing` counts as starting to play, sessions that don't say stay out of it.
+    let playing = state.sessions.get(&step.session).is_some_and(|props| props.status == PlaybackStatus::Playing);
+    if playing && !was_playing {
+        state.current = Some(step.session.clone());
+    } else if !playing && state.current.as_ref() == Some(&step.session) {
+        state.current = None;
+    }
+    state.subscribers.retain(|tx| tx.send(event.clone()).is_ok());
+    Some(event)
+}
+
+impl MediaSource for ScriptedSource {
+    fn sessions(&self) -> Result<Vec<String>, Error> {
+        Ok(self.state.lock().unwrap().sessions.keys().cloned().collect())
+    }
+
+    fn props(&self, session: &str) -> Result<SpectreProps, Error> {
+        self.state.lock().unwrap().sessions.get(session).cloned()
+            .ok_or(Error::new(ErrorKind::NotFound, format!("No session for {}", session)))
+    }
+
+    fn current_session(&self) -> Result<Option<String>, Error> {
+        Ok(self.state.lock().unwrap().current.clone())
+    }
+
+    fn subscribe(&self, tx: Sender<MediaEvent>) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        for session in state.sessions.keys() {
+            let _ = tx.send(MediaEvent::SessionAdded(session.clone()));
+        }
+        state.subscribers.push(tx);
+        if state.playing {
+            return Ok(());
+        }
+        state.playing = true;
+        let (steps, base_dir, shared) = (self.steps.clone(), self.base_dir.clone(), self.state.clone());
+        thread::spawn(move || {
+            let state = shared;
+            let mut now = 0.0;
+            loop {
+                let next_at = {
+                    let state = state.lock().unwrap();
+                    match steps.get(state.next) {
+                        Some(step) => step.at,
+                        None => break,
+                    }
+                };
+                if next_at > now {
+                    thread::sleep(Duration::from_secs_f32(next_at - now));
+                    now = next_at;
+                }
+                apply_next(&steps, &base_dir, &state);
+            }
+            // Script's over, hang up so anything waiting on events knows.
+            state.lock().unwrap().subscribers.clear();
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+    }
+
+    #[test]
+    fn steps_become_events() {
+        let source = ScriptedSource::load(&fixture("track_changes.toml")).unwrap();
+        let events: Vec<MediaEvent> = std::iter::from_fn(|| source.advance()).collect();
+        let ev = |f: fn(String) -> MediaEvent, s: &str| f(s.to_string());
+        assert_eq!(events, vec![
+            ev(MediaEvent::SessionAdded, "spotify"),
+            ev(MediaEvent::PlaybackChanged, "spotify"),
+            ev(MediaEvent::PropsChanged, "spotify"),
+            ev(MediaEvent::SessionAdded, "browser"),
+            ev(MediaEvent::PropsChanged, "spotify"),
+            ev(MediaEvent::SessionRemoved, "browser"),
+        ]);
+        assert_eq!(source.sessions().unwrap(), vec!["spotify".to_string()]);
+        let props = source.props("spotify").unwrap();
+        assert_eq!(props.title, "Violent Crimes");
+        assert_eq!(props.track_count, Some(7));
+        assert_eq!(props.playback_type, SPT::AUDIO);
+        assert_eq!((props.source.id.as_str(), props.source.name.as_str()), ("spotify", "Spotify"));
+        assert!(source.props("browser").is_err());
+    }
+
+    #[test]
+    fn thumbnails_are_fitted() {
+        let source = ScriptedSource::load(&fixture("track_changes.toml")).unwrap();
+        for _ in 0..4 {
+            source.advance();
+        }
+        let spotify = source.props("spotify").unwrap().thumbnail;
+        let browser = source.props("browser").unwrap().thumbnail;
+        assert_eq!((spotify.width(), spotify.height()), (THUMB_W, THUMB_H));
+        assert_eq!((browser.width(), browser.height()), (THUMB_W, THUMB_H));
+        // Wide art gets letterboxed onto a transparent background.
+        assert_eq!(browser.to_rgba8().get_pixel(0, 0).0[3], 0);
+        assert_eq!(browser.to_rgba8().get_pixel(THUMB_W / 2, THUMB_H / 2).0[3], 255);
+    }
+
+    #[test]
+    fn missing_art_gets_error_thumb() {
+        let source = ScriptedSource::load(&fixture("missing_art.json")).unwrap();
+        source.advance();
+        assert!(source.props("vlc").unwrap().thumbnail == *ERROR_THUMB);
+        source.advance();
+        assert!(source.props("vlc").unwrap().thumbnail == *ERROR_THUMB);
+    }
+
+    #[test]
+    fn subscribers_get_hung_up_at_the_end() {
+        let source = ScriptedSource::load(&fixture("track_changes.toml")).unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        source.subscribe(tx).unwrap();
+        assert_eq!(rx.iter().count(), 6);
+    }
+}
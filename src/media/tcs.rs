@@ -0,0 +1,178 @@
+use super::{MediaEvent, MediaSource};
+use crate::props::*;
+use futures::executor::block_on;
+use std::{collections::HashMap, io::{Error, ErrorKind}, result::Result, sync::{mpsc::Sender, Arc, Mutex}};
+use windows::core::{Interface, HSTRING};
+use windows::ApplicationModel::AppInfo;
+use windows::Foundation::{Size, TypedEventHandler};
+use windows::Media::Control::GlobalSystemMediaTransportControlsSessionManager as TCSManager;
+pub use windows::Media::Control::{
+    GlobalSystemMediaTransportControlsSession as TCS,
+    GlobalSystemMediaTransportControlsSessionMediaProperties as TCSProperties};
+
+async fn get_tcs_manager() -> Result<TCSManager, Error> {
+    let manager: TCSManager = TCSManager::RequestAsync()?.get()?;
+    Ok(manager)
+}
+/// Gets the media properties for the provided `TCS` (Global System Media Transport Controls Session).
+///
+/// This function retrieves the media properties for the given `TCS` session, such as title, artist, album, etc.
+///
+/// # Arguments
+/// * `sesh` - The `TCS` session to get the media properties for.
+///
+/// # Returns
+/// A `Result` containing the `TCSProperties` for the provided `TCS` session, or an `Error` if the operation fails.
+async fn get_tcs_props(sesh: &TCS) -> Result<TCSProperties, Error> {
+    let props: TCSProperties = sesh.TryGetMediaPropertiesAsync()?.get()?;
+    Ok(props)
+}
+
+/// Every `TCS` seen so far and the id it was given.
+///
+/// A session's id is the AUMID of the app that owns it, but an app can have more than one session (a browser with two
+/// tabs playing), so the ones after the first get `#2`, `#3`... on the end. Sessions are told apart by their COM
+/// identity, which is what `==` on a `TCS` compares, so an id sticks to its session for as long as the session lives.
+#[derive(Default)]
+struct Sessions {
+    ids: Vec<(String, TCS)>,
+    // Sessions we've hooked events on, with the id their handlers send. Kept by session rather than by id, since a
+    // new session can get the id of one that's gone before `watch_sessions` gets to hear about it.
+    hooked: Vec<(String, TCS)>,
+}
+impl Sessions {
+    fn id(&mut self, sesh: &TCS) -> Result<String, Error> {
+        if let Some((id, _)) = self.ids.iter().find(|(_, known)| known == sesh) {
+            return Ok(id.clone());
+        }
+        let aumid = sesh.SourceAppUserModelId()?.to_string();
+        let id = (1..)
+            .map(|n| if n == 1 { aumid.clone() } else { format!("{}#{}", aumid, n) })
+            .find(|id| self.ids.iter().all(|(known, _)| known != id))
+            .unwrap();
+        self.ids.push((id.clone(), sesh.clone()));
+        Ok(id)
+    }
+
+    /// Catches up with the sessions `manager` has right now, forgetting the ones that are gone.
+    fn refresh(&mut self, manager: &TCSManager) -> Result<Vec<(String, TCS)>, Error> {
+        let sessions: Vec<TCS> = manager.GetSessions()?.into_iter().collect();
+        self.ids.retain(|(_, known)| sessions.contains(known));
+        sessions.into_iter().map(|sesh| Ok((self.id(&sesh)?, sesh))).collect()
+    }
+}
+
+/// Looks up the app behind an AUMID. Only packaged apps have an `AppInfo`, everything else just gets named after its id.
+fn resolve_app(aumid: &str) -> SourceApp {
+    let Ok(display) = AppInfo::GetFromAppUserModelId(&HSTRING::from(aumid)).and_then(|info| info.DisplayInfo()) else {
+        return SourceApp::from_id(aumid);
+    };
+    let name = display.DisplayName().map(|name| name.to_string()).ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| name_from_id(aumid));
+    let icon = display.GetLogo(Size { Width: 64.0, Height: 64.0 }).ok()
+        .and_then(|logo| logo.cast::<StreamRef>().ok())
+        .and_then(|logo| read_stream_ref(Some(logo)).ok().and_then(|data| image::load_from_memory(&data).ok()));
+    SourceApp { id: aumid.to_string(), name, icon }
+}
+
+/// `MediaSource` backed by the Windows Global System Media Transport Controls session manager.
+pub struct TcsSource {
+    manager: TCSManager,
+    sessions: Arc<Mutex<Sessions>>,
+    // Looking an app up means loading its logo, so each one only gets done once.
+    apps: Mutex<HashMap<String, SourceApp>>,
+}
+impl TcsSource {
+    pub fn new() -> Result<Self, Error> {
+        let manager = block_on(get_tcs_manager())?;
+        Ok(Self { manager, sessions: Arc::new(Mutex::new(Sessions::default())), apps: Mutex::new(HashMap::new()) })
+    }
+
+    fn app(&self, aumid: &str) -> SourceApp {
+        self.apps.lock().unwrap().entry(aumid.to_string()).or_insert_with(|| resolve_app(aumid)).clone()
+    }
+
+    fn find(&self, session: &str) -> Result<TCS, Error> {
+        let alive = self.sessions.lock().unwrap().refresh(&self.manager)?;
+        alive.into_iter().find(|(id, _)| id == session).map(|(_, sesh)| sesh)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("No session for {}", session)))
+    }
+}
+
+/// Hooks the property and playback events of every session of `manager` that isn't already hooked,
+/// and forgets the ones that are gone. Sends `SessionAdded`/`SessionRemoved` for the difference.
+fn watch_sessions(manager: &TCSManager, sessions: &Mutex<Sessions>, tx: &Sender<MediaEvent>) -> Result<(), Error> {
+    let mut sessions = sessions.lock().unwrap();
+    let alive = sessions.refresh(manager)?;
+    sessions.hooked.retain(|(id, hooked)| {
+        let keep = alive.iter().any(|(_, sesh)| sesh == hooked);
+        if !keep {
+            let _ = tx.send(MediaEvent::SessionRemoved(id.clone()));
+        }
+        keep
+    });
+    for (id, sesh) in &alive {
+        if sessions.hooked.iter().any(|(_, hooked)| hooked == sesh) {
+            continue;
+        }
+        let (props_tx, props_id) = (tx.clone(), id.clone());
+        sesh.MediaPropertiesChanged(&TypedEventHandler::new(move |_, _| {
+            let _ = props_tx.send(MediaEvent::PropsChanged(props_id.clone()));
+            Ok(())
+        }))?;
+        let (play_tx, play_id) = (tx.clone(), id.clone());
+        sesh.PlaybackInfoChanged(&TypedEventHandler::new(move |_, _| {
+            let _ = play_tx.send(MediaEvent::PlaybackChanged(play_id.clone()));
+            Ok(())
+        }))?;
+        sessions.hooked.push((id.clone(), sesh.clone()));
+        let _ = tx.send(MediaEvent::SessionAdded(id.clone()));
+    }
+    Ok(())
+}
+
+impl MediaSource for TcsSource {
+    fn sessions(&self) -> Result<Vec<String>, Error> {
+        let alive = self.sessions.lock().unwrap().refresh(&self.manager)?;
+        Ok(alive.into_iter().map(|(id, _)| id).collect())
+    }
+
+    fn props(&self, session: &str) -> Result<SpectreProps, Error> {
+        let sesh = self.find(session)?;
+        let props = block_on(get_tcs_props(&sesh))?;
+        let mut spectre_props = SpectreProps::from_tcsp(props);
+        spectre_props.sync_playback(&sesh);
+        spectre_props.source = self.app(&sesh.SourceAppUserModelId()?.to_string());
+        Ok(spectre_props)
+    }
+
+    fn current_session(&self) -> Result<Option<String>, Error> {
+        // No current session is an error rather than a null here.
+        match self.manager.GetCurrentSession() {
+            Ok(sesh) => Ok(Some(self.sessions.lock().unwrap().id(&sesh)?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn subscribe(&self, tx: Sender<MediaEvent>) -> Result<(), Error> {
+        watch_sessions(&self.manager, &self.sessions, &tx)?;
+        // Whoever just became current might have been playing all along, give the watcher a reason to look at it.
+        let (current_tx, current_sessions) = (tx.clone(), self.sessions.clone());
+        self.manager.CurrentSessionChanged(&TypedEventHandler::new(move |manager: &Option<TCSManager>, _| {
+            let current = manager.as_ref().and_then(|m| m.GetCurrentSession().ok());
+            if let Some(id) = current.and_then(|sesh| current_sessions.lock().unwrap().id(&sesh).ok()) {
+                let _ = current_tx.send(MediaEvent::PlaybackChanged(id));
+            }
+            Ok(())
+        }))?;
+        let sessions = self.sessions.clone();
+        self.manager.SessionsChanged(&TypedEventHandler::new(move |manager: &Option<TCSManager>, _| {
+            if let Some(manager) = manager {
+                let _ = watch_sessions(manager, &sessions, &tx);
+            }
+            Ok(())
+        }))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+//! Tracks as desktop notifications, through `org.freedesktop.Notifications` on the session bus.
+//!
+//! For when the desktop's own notifications are preferred over the overlay. Each session keeps one notification
+//! that gets updated on every track change, rather than piling a new one up each time.
+use crate::props::*;
+use crate::rules::ToastSettings;
+use std::{collections::HashMap, io::Error, result::Result};
+use zbus::{blocking::Connection, zvariant::Value};
+
+const APP_NAME: &str = "Song Spectre";
+const DEST: &str = "org.freedesktop.Notifications";
+const PATH: &str = "/org/freedesktop/Notifications";
+/// Not one of the spec's categories, there's none for music, but it's what GNOME and KDE players send.
+const CATEGORY: &str = "x-gnome.music";
+
+pub struct Notifier {
+    conn: Connection,
+    /// The notification each session last got, to replace on its next track.
+    ids: HashMap<String, u32>,
+    /// Whether the server takes markup in bodies, asked the first time it matters.
+    body_markup: Option<bool>,
+}
+
+impl Notifier {
+    /// Connects to the user's session bus.
+    pub fn new() -> Result<Self, Error> {
+        Ok(Self::with_connection(Connection::session().map_err(Error::other)?))
+    }
+
+    pub fn with_connection(conn: Connection) -> Self {
+        Notifier { conn, ids: HashMap::new(), body_markup: None }
+    }
+
+    /// Shows `props` as a notification for `session`, replacing the one it had. Returns the notification's id.
+    pub fn notify(&mut self, session: &str, props: &SpectreProps, settings: &ToastSettings) -> Result<u32, Error> {
+        let thumb = props.thumbnail.to_rgba8();
+        let (width, height) = (thumb.width() as i32, thumb.height() as i32);
+        // (width, height, rowstride, has alpha, bits per sample, channels, pixels)
+        let image = Value::from((width, height, width * 4, true, 8i32, 4i32, thumb.into_raw()));
+        let hints = HashMap::from([
+            ("image-data", image),
+            ("category", Value::from(CATEGORY)),
+        ]);
+        let replaces = self.ids.get(session).copied().unwrap_or(0);
+        let timeout = (settings.timeline().duration() * 1000.0).round() as i32;
+        // The summary is always plain text, only the body can have markup in it.
+        let text = if self.body_markup() { escape(&body(props)) } else { body(props) };
+        let body = (APP_NAME, replaces, "", props.title.as_str(), text, Vec::<&str>::new(), hints, timeout);
+        let id: u32 = self.conn.call_method(Some(DEST), PATH, Some(DEST), "Notify", &body)
+            .map_err(Error::other)?
+            .body().deserialize().map_err(Error::other)?;
+        self.ids.insert(session.to_string(), id);
+        Ok(id)
+    }
+
+    fn body_markup(&mut self) -> bool {
+        let conn = &self.conn;
+        *self.body_markup.get_or_insert_with(|| capabilities(conn).iter().any(|capability| capability == "body-markup"))
+    }
+}
+
+/// What the server can do, from `GetCapabilities`. Nothing if it won't say.
+fn capabilities(conn: &Connection) -> Vec<String> {
+    conn.call_method(Some(DEST), PATH, Some(DEST), "GetCapabilities", &())
+        .and_then(|reply| reply.body().deserialize())
+        .unwrap_or_default()
+}
+
+/// Artist and album, a line each, leaving out whichever isn't known.
+fn body(props: &SpectreProps) -> String {
+    [(&props.artist, UNKNOWN_ARTIST), (&props.album, UNKNOWN_ALBUM)].iter()
+        .filter(|(value, unknown)| !value.is_empty() && value != unknown)
+        .map(|(value, _)| value.as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Servers that do markup would otherwise take an `&` or `<` in a body as the start of some.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_bus::TestBus;
+    use image::DynamicImage;
+    use std::sync::{Arc, Mutex};
+    use zbus::{blocking::connection::Builder as ConnBuilder, zvariant::OwnedValue};
+
+    #[derive(Debug)]
+    struct Call {
+        replaces_id: u32,
+        summary: String,
+        body: String,
+        hints: HashMap<String, OwnedValue>,
+        expire_timeout: i32,
+    }
+
+    /// Just enough of a notification server to see what it gets sent.
+    struct StubServer {
+        calls: Arc<Mutex<Vec<Call>>>,
+        next_id: u32,
+        markup: Arc<Mutex<bool>>,
+    }
+    #[zbus::interface(name = "org.freedesktop.Notifications")]
+    impl StubServer {
+        fn get_capabilities(&self) -> Vec<&str> {
+            if *self.markup.lock().unwrap() { vec!["body", "body-markup"] } else { vec!["body"] }
+        }
+
+        #[allow(clippy::too_many_arguments)]
+        fn notify(&mut self, _app_name: String, replaces_id: u32, _app_icon: String, summary: String, body: String,
+            _actions: Vec<String>, hints: HashMap<String, OwnedValue>, expire_timeout: i32) -> u32 {
+            self.calls.lock().unwrap().push(Call { replaces_id, summary, body, hints, expire_timeout });
+            if replaces_id != 0 {
+                return replaces_id;
+            }
+            self.next_id += 1;
+            self.next_id
+        }
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon, run with --ignored"]
+    fn replaces_each_sessions_notification() {
+        let bus = TestBus::start();
+        let (calls, markup) = (Arc::new(Mutex::new(Vec::new())), Arc::new(Mutex::new(true)));
+        let _server = ConnBuilder::address(bus.address.as_str()).unwrap()
+            .name(DEST).unwrap()
+            .serve_at(PATH, StubServer { calls: calls.clone(), next_id: 0, markup: markup.clone() }).unwrap()
+            .build().unwrap();
+        let mut notifier = Notifier::with_connection(bus.connect());
+        let settings = ToastSettings::from(&crate::config::Config::default());
+        let props = SpectreProps {
+            title: "Rock & Roll".to_string(),
+            artist: "Page & Plant".to_string(),
+            thumbnail: DynamicImage::new_rgba8(3, 2),
+            ..SpectreProps::default()
+        };
+
+        let first = notifier.notify("spotify", &props, &settings).unwrap();
+        let next = SpectreProps { title: "The Battle of Evermore".to_string(), ..props.clone() };
+        assert_eq!(notifier.notify("spotify", &next, &settings).unwrap(), first);
+        assert_ne!(notifier.notify("browser", &props, &settings).unwrap(), first);
+
+        *markup.lock().unwrap() = false;
+        Notifier::with_connection(bus.connect()).notify("spotify", &props, &settings).unwrap();
+
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.iter().map(|call| call.replaces_id).collect::<Vec<_>>(), [0, first, 0, 0]);
+        let call = &calls[0];
+        assert_eq!((call.summary.as_str(), call.body.as_str()), ("Rock & Roll", "Page &amp; Plant"));
+        assert_eq!((calls[3].summary.as_str(), calls[3].body.as_str()), ("Rock & Roll", "Page & Plant"));
+        assert_eq!(call.expire_timeout, (settings.timeline().duration() * 1000.0).round() as i32);
+        assert_eq!(call.hints["category"], OwnedValue::from(zbus::zvariant::Str::from(CATEGORY)));
+        let (width, height, rowstride, alpha, bits, channels, data): (i32, i32, i32, bool, i32, i32, Vec<u8>) =
+            call.hints["image-data"].try_clone().unwrap().try_into().unwrap();
+        assert_eq!((width, height, rowstride, alpha, bits, channels, data.len()), (3, 2, 12, true, 8, 4, 24));
+    }
+}
@@ -0,0 +1,76 @@
+//! Toast windows for Linux desktops, what `Ghoast` is on Windows.
+//!
+//! Each one shows an already rendered toast frame with per-pixel alpha, stays above everything else and lets clicks
+//! through to whatever's under it. Where it goes and how it moves is up to `toasts`, `placement` and `anim`.
+pub mod wayland;
+pub mod x11;
+pub use wayland::*;
+pub use x11::*;
+
+use crate::anim::Frame;
+use crate::config::Position;
+use crate::placement::Monitor;
+use image::RgbaImage;
+use std::{io::Error, result::Result};
+
+/// Whichever display server there is to show toasts on.
+pub enum Display {
+    Wayland(Wayland),
+    X11(X11),
+}
+
+impl Display {
+    /// A Wayland compositor with layer-shell if there is one, otherwise X11, which includes XWayland on the others.
+    pub fn connect() -> Result<Self, Error> {
+        match Wayland::connect() {
+            Ok(wayland) => Ok(Display::Wayland(wayland)),
+            Err(_) => X11::connect().map(Display::X11),
+        }
+    }
+
+    pub fn monitors(&self) -> Vec<Monitor> {
+        match self {
+            Display::Wayland(wayland) => wayland.monitors(),
+            Display::X11(x11) => x11.monitors(),
+        }
+    }
+
+    /// Where the mouse is. Wayland doesn't say unless it's over one of our surfaces, which toasts never are.
+    pub fn cursor(&self) -> Option<(i32, i32)> {
+        match self {
+            Display::Wayland(_) => None,
+            Display::X11(x11) => x11.cursor(),
+        }
+    }
+
+    /// Shows `frame` as a toast on `monitor` with its top left corner at `origin`, fully transparent until it gets `apply`ed.
+    pub fn toast(self, frame: RgbaImage, monitor: &Monitor, position: &Position, origin: (i32, i32)) -> Result<Toast, Error> {
+        match self {
+            Display::Wayland(wayland) => wayland.toast(frame, monitor, position.anchor, origin).map(Toast::Wayland),
+            Display::X11(x11) => x11.toast(frame, origin).map(Toast::X11),
+        }
+    }
+}
+
+pub enum Toast {
+    Wayland(WaylandToast),
+    X11(X11Toast),
+}
+
+impl Toast {
+    /// Swaps what the toast shows for `image`, the same size, from the next `apply` on.
+    pub fn set_image(&mut self, image: RgbaImage) {
+        match self {
+            Toast::Wayland(toast) => toast.set_image(image),
+            Toast::X11(toast) => toast.set_image(image),
+        }
+    }
+
+    /// Shows `frame`, with the toast's resting spot at `origin`.
+    pub fn apply(&mut self, frame: Frame, origin: (i32, i32)) -> Result<(), Error> {
+        match self {
+            Toast::Wayland(toast) => toast.apply(frame, origin),
+            Toast::X11(toast) => toast.apply(frame, origin),
+        }
+    }
+}
@@ -0,0 +1,363 @@
+//! Toasts as `wlr-layer-shell` surfaces on the overlay layer, for wlroots compositors like Sway and Hyprland.
+//!
+//! A layer surface can't be put at a position, only anchored to edges of an output and pushed off them by margins.
+//! So the toast is anchored the way the config says, and its spot from `ToastManager` is turned into margins.
+use crate::render::premultiply;
+use crate::anim::Frame;
+use crate::config::Anchor;
+use crate::placement::{Monitor, Rect};
+use image::{imageops::{self, FilterType}, RgbaImage};
+use smithay_client_toolkit::{
+    compositor::{CompositorHandler, CompositorState, Region},
+    delegate_compositor, delegate_layer, delegate_output, delegate_registry, delegate_shm,
+    output::{OutputHandler, OutputState},
+    registry::{ProvidesRegistryState, RegistryState},
+    registry_handlers,
+    shell::{wlr_layer::{self, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface, LayerSurfaceConfigure},
+        WaylandSurface},
+    shm::{slot::{Buffer, SlotPool}, Shm, ShmHandler},
+};
+use smithay_client_toolkit::reexports::client::{
+    backend::WaylandError,
+    globals::registry_queue_init,
+    protocol::{wl_output::{self, WlOutput}, wl_shm, wl_surface::WlSurface},
+    Connection, EventQueue, QueueHandle,
+};
+use std::{io::{Error, ErrorKind}, os::unix::net::UnixStream, path::Path, result::Result};
+
+/// Margins (top, right, bottom, left) that put a `size` surface anchored by `anchor` at `origin` on `monitor`.
+/// Centered anchors get anchored to both sides, which puts the surface between the two margins.
+pub fn margins(anchor: Anchor, monitor: &Rect, origin: (i32, i32), size: (u32, u32)) -> (i32, i32, i32, i32) {
+    let top = origin.1 - monitor.y;
+    let left = origin.0 - monitor.x;
+    let bottom = monitor.y + monitor.height as i32 - origin.1 - size.1 as i32;
+    let right = monitor.x + monitor.width as i32 - origin.0 - size.0 as i32;
+    match anchor {
+        Anchor::TopLeft => (top, 0, 0, left),
+        Anchor::Top => (top, right, 0, left),
+        Anchor::TopRight => (top, right, 0, 0),
+        Anchor::BottomLeft => (0, 0, bottom, left),
+        Anchor::Bottom => (0, right, bottom, left),
+        Anchor::BottomRight => (0, right, bottom, 0),
+    }
+}
+
+fn layer_anchor(anchor: Anchor) -> wlr_layer::Anchor {
+    use wlr_layer::Anchor as Edge;
+    match anchor {
+        Anchor::TopLeft => Edge::TOP | Edge::LEFT,
+        Anchor::Top => Edge::TOP | Edge::LEFT | Edge::RIGHT,
+        Anchor::TopRight => Edge::TOP | Edge::RIGHT,
+        Anchor::BottomLeft => Edge::BOTTOM | Edge::LEFT,
+        Anchor::Bottom => Edge::BOTTOM | Edge::LEFT | Edge::RIGHT,
+        Anchor::BottomRight => Edge::BOTTOM | Edge::RIGHT,
+    }
+}
+
+struct State {
+    registry: RegistryState,
+    outputs: OutputState,
+    shm: Shm,
+    configured: bool,
+    closed: bool,
+}
+
+/// A connection to the compositor, for working out where a toast goes before it's made.
+pub struct Wayland {
+    conn: Connection,
+    queue: EventQueue<State>,
+    state: State,
+    compositor: CompositorState,
+    layer_shell: LayerShell,
+}
+
+impl Wayland {
+    /// Connects to the compositor in `WAYLAND_DISPLAY`.
+    pub fn connect() -> Result<Self, Error> {
+        Self::init(Connection::connect_to_env().map_err(|e| Error::new(ErrorKind::NotConnected, e))?)
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn connect_to(socket: &Path) -> Result<Self, Error> {
+        Self::init(Connection::from_socket(UnixStream::connect(socket)?).map_err(Error::other)?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, Error> {
+        let (globals, mut queue) = registry_queue_init::<State>(&conn).map_err(Error::other)?;
+        let qh = queue.handle();
+        let unsupported = |what: &str| Error::new(ErrorKind::Unsupported, format!("The compositor has no {}", what));
+        let compositor = CompositorState::bind(&globals, &qh).map_err(|_| unsupported("wl_compositor"))?;
+        let layer_shell = LayerShell::bind(&globals, &qh).map_err(|_| unsupported("wlr-layer-shell"))?;
+        let shm = Shm::bind(&globals, &qh).map_err(|_| unsupported("wl_shm"))?;
+        let mut state = State {
+            registry: RegistryState::new(&globals),
+            outputs: OutputState::new(&globals, &qh),
+            shm,
+            configured: false,
+            closed: false,
+        };
+        // Outputs announce themselves, then what they're like.
+        queue.roundtrip(&mut state).map_err(Error::other)?;
+        queue.roundtrip(&mut state).map_err(Error::other)?;
+        Ok(Wayland { conn, queue, state, compositor, layer_shell })
+    }
+
+    /// Every output, in physical pixels so they match the scaled toasts. Wayland has no primary output,
+    /// so the first one stands in.
+    fn outputs(&self) -> Vec<(Monitor, WlOutput)> {
+        let mut outputs: Vec<_> = self.state.outputs.outputs().filter_map(|output| {
+            let info = self.state.outputs.info(&output)?;
+            let ((x, y), (width, height)) = (info.logical_position?, info.logical_size?);
+            let scale = info.scale_factor.max(1);
+            let work = Rect { x: x * scale, y: y * scale, width: (width * scale) as u32, height: (height * scale) as u32 };
+            Some((Monitor { work, scale: scale as f32, primary: false }, output))
+        }).collect();
+        if let Some((first, _)) = outputs.first_mut() {
+            first.primary = true;
+        }
+        outputs
+    }
+
+    pub fn monitors(&self) -> Vec<Monitor> {
+        self.outputs().into_iter().map(|(monitor, _)| monitor).collect()
+    }
+
+    /// Shows `frame` as a toast on `monitor` with its top left corner at `origin`, fully transparent until it gets `apply`ed.
+    pub fn toast(self, frame: RgbaImage, monitor: &Monitor, anchor: Anchor, origin: (i32, i32)) -> Result<WaylandToast, Error> {
+        WaylandToast::new(self, frame, monitor, anchor, origin)
+    }
+}
+
+/// A toast surface. Goes away when dropped.
+pub struct WaylandToast {
+    conn: Connection,
+    queue: EventQueue<State>,
+    state: State,
+    layer: Option<LayerSurface>,
+    pool: SlotPool,
+    /// At most two: the one on screen, which the compositor might still be reading, and the one drawn next.
+    buffers: Vec<Buffer>,
+    _input: Region,
+    frame: RgbaImage,
+    monitor: Monitor,
+    anchor: Anchor,
+}
+
+impl WaylandToast {
+    fn new(wayland: Wayland, frame: RgbaImage, monitor: &Monitor, anchor: Anchor, origin: (i32, i32)) -> Result<Self, Error> {
+        let output = wayland.outputs().into_iter().find(|(m, _)| m == monitor).map(|(_, output)| output);
+        let Wayland { conn, queue, state, compositor, layer_shell } = wayland;
+        let qh = queue.handle();
+        let surface = compositor.create_surface(&qh);
+        let layer = layer_shell.create_layer_surface(&qh, surface, Layer::Overlay, Some("song_spectre"), output.as_ref());
+        layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+        layer.set_anchor(layer_anchor(anchor));
+        // Margins are from the output's edges, like the monitor rects they're worked out from, not from inside panels.
+        layer.set_exclusive_zone(-1);
+        // Nothing in the input region, so clicks land on whatever's underneath.
+        let input = Region::new(&compositor).map_err(Error::other)?;
+        layer.wl_surface().set_input_region(Some(input.wl_region()));
+        layer.wl_surface().set_buffer_scale(monitor.scale as i32);
+        let pool = SlotPool::new((frame.width() * frame.height() * 4) as usize, &state.shm).map_err(Error::other)?;
+
+        let mut toast = WaylandToast {
+            conn, queue, state, layer: Some(layer), pool, buffers: Vec::new(),
+            _input: input, frame, monitor: *monitor, anchor,
+        };
+        toast.place(origin, toast.frame.dimensions());
+        toast.layer().commit();
+        while !toast.state.configured {
+            if toast.state.closed {
+                return Err(Error::new(ErrorKind::Interrupted, "The compositor closed the toast"));
+            }
+            toast.queue.blocking_dispatch(&mut toast.state).map_err(Error::other)?;
+        }
+        let frame = toast.frame.clone();
+        toast.draw(&frame, 0)?;
+        Ok(toast)
+    }
+
+    fn layer(&self) -> &LayerSurface {
+        self.layer.as_ref().unwrap()
+    }
+
+    /// Moves the surface so a `size` toast's top left corner is at `origin`.
+    fn place(&self, origin: (i32, i32), size: (u32, u32)) {
+        let scale = self.monitor.scale as i32;
+        let (top, right, bottom, left) = margins(self.anchor, &self.monitor.work, origin, size);
+        self.layer().set_margin(top / scale, right / scale, bottom / scale, left / scale);
+        self.layer().set_size(size.0 / scale as u32, size.1 / scale as u32);
+    }
+
+    /// Reads whatever the compositor has sent without waiting for more, so released buffers get reused
+    /// and a `closed` gets noticed.
+    fn read_events(&mut self) -> Result<(), Error> {
+        self.conn.flush().map_err(Error::other)?;
+        if let Some(guard) = self.queue.prepare_read() {
+            match guard.read() {
+                Ok(_) => {}
+                Err(WaylandError::Io(e)) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(Error::other(e)),
+            }
+        }
+        self.queue.dispatch_pending(&mut self.state).map_err(Error::other)?;
+        Ok(())
+    }
+
+    /// Index into `buffers` of one for a `width` by `height` image that the compositor is done with.
+    /// `None` if it's still reading both.
+    fn free_buffer(&mut self, width: u32, height: u32) -> Result<Option<usize>, Error> {
+        let (pool, size) = (&mut self.pool, (width as i32 * 4, height as i32));
+        if let Some(i) = self.buffers.iter().position(|b| (b.stride(), b.height()) == size && b.canvas(pool).is_some()) {
+            return Ok(Some(i));
+        }
+        // One that's free but the wrong size, from before the toast got scaled, makes way for a new one.
+        if let Some(i) = self.buffers.iter().position(|b| b.canvas(pool).is_some()) {
+            self.buffers.swap_remove(i);
+        }
+        if self.buffers.len() >= 2 {
+            return Ok(None);
+        }
+        let (buffer, _) = self.pool.create_buffer(width as i32, height as i32, width as i32 * 4, wl_shm::Format::Argb8888)
+            .map_err(Error::other)?;
+        self.buffers.push(buffer);
+        Ok(Some(self.buffers.len() - 1))
+    }
+
+    fn draw(&mut self, image: &RgbaImage, opacity: u8) -> Result<(), Error> {
+        let (width, height) = image.dimensions();
+        // The compositor's behind, so this frame gets skipped rather than piling up buffers.
+        let Some(i) = self.free_buffer(width, height)? else { return Ok(()) };
+        let buffer = &self.buffers[i];
+        let canvas = buffer.canvas(&mut self.pool).unwrap();
+        // Argb8888 is little endian, so BGRA in memory.
+        for (out, pixel) in canvas.chunks_exact_mut(4).zip(image.pixels()) {
+            let [r, g, b, a] = premultiply(pixel.0, opacity);
+            out.copy_from_slice(&[b, g, r, a]);
+        }
+        let surface = self.layer().wl_surface();
+        surface.damage_buffer(0, 0, width as i32, height as i32);
+        buffer.attach_to(surface).map_err(Error::other)?;
+        self.layer().commit();
+        self.conn.flush().map_err(Error::other)
+    }
+
+    /// Swaps what the toast shows for `image`, the same size, from the next `apply` on.
+    pub fn set_image(&mut self, image: RgbaImage) {
+        self.frame = image;
+    }
+
+    /// Shows `frame`, with the toast's resting spot at `origin`.
+    pub fn apply(&mut self, frame: Frame, origin: (i32, i32)) -> Result<(), Error> {
+        self.read_events()?;
+        if self.state.closed {
+            return Err(Error::new(ErrorKind::Interrupted, "The compositor closed the toast"));
+        }
+        let (width, height) = (self.frame.width() as f32, self.frame.height() as f32);
+        // Buffers have to come in whole multiples of the buffer scale.
+        let step = self.monitor.scale.max(1.0);
+        let scaled = |size: f32| ((size * frame.scale / step).round().max(1.0) * step) as u32;
+        let size = (scaled(width), scaled(height));
+        let x = origin.0 + frame.offset.0 + ((width - size.0 as f32) / 2.0).round() as i32;
+        let y = origin.1 + frame.offset.1 + ((height - size.1 as f32) / 2.0).round() as i32;
+        self.place((x, y), size);
+        if size == self.frame.dimensions() {
+            let image = self.frame.clone();
+            self.draw(&image, frame.opacity)
+        } else {
+            self.draw(&imageops::resize(&self.frame, size.0, size.1, FilterType::Triangle), frame.opacity)
+        }
+    }
+}
+
+impl Drop for WaylandToast {
+    fn drop(&mut self) {
+        self.layer.take();
+        let _ = self.conn.flush();
+    }
+}
+
+impl CompositorHandler for State {
+    fn scale_factor_changed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlSurface, _: i32) {}
+    fn transform_changed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlSurface, _: wl_output::Transform) {}
+    fn frame(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlSurface, _: u32) {}
+    fn surface_enter(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlSurface, _: &WlOutput) {}
+    fn surface_leave(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &WlSurface, _: &WlOutput) {}
+}
+
+impl OutputHandler for State {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.outputs
+    }
+    fn new_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: WlOutput) {}
+    fn update_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: WlOutput) {}
+    fn output_destroyed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: WlOutput) {}
+}
+
+impl LayerShellHandler for State {
+    fn closed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &LayerSurface) {
+        self.closed = true;
+    }
+    // The size is ours to pick, so there's nothing to do but note it happened.
+    fn configure(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &LayerSurface, _: LayerSurfaceConfigure, _: u32) {
+        self.configured = true;
+    }
+}
+
+impl ShmHandler for State {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm
+    }
+}
+
+impl ProvidesRegistryState for State {
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry
+    }
+    registry_handlers![OutputState];
+}
+
+delegate_compositor!(State);
+delegate_output!(State);
+delegate_shm!(State);
+delegate_layer!(State);
+delegate_registry!(State);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_compositor::TestCompositor;
+
+    #[test]
+    fn margins_off_the_anchored_edges() {
+        let monitor = Rect { x: 1920, y: 0, width: 1920, height: 1080 };
+        let origin = (1920 + 1920 - 480 - 24, 1080 - 160 - 40);
+        assert_eq!(margins(Anchor::BottomRight, &monitor, origin, (480, 160)), (0, 24, 40, 0));
+        assert_eq!(margins(Anchor::TopLeft, &monitor, (1920 + 30, 20), (480, 160)), (20, 0, 0, 30));
+        // Centered ones sit between their left and right margins.
+        assert_eq!(margins(Anchor::Top, &monitor, (1920 + 720, 24), (480, 160)), (24, 720, 0, 720));
+        // Sliding in from off screen.
+        assert_eq!(margins(Anchor::BottomLeft, &monitor, (1920 - 100, 900), (480, 160)), (0, 0, 20, -100));
+    }
+
+    #[test]
+    #[ignore = "needs sway, run with --ignored"]
+    fn shows_layer_surfaces() {
+        let compositor = TestCompositor::start();
+        let wayland = Wayland::connect_to(&compositor.socket).unwrap();
+        let monitor = wayland.monitors()[0];
+        assert!(monitor.primary && monitor.work.width > 0);
+
+        let frame = RgbaImage::from_pixel(40, 20, image::Rgba([200, 100, 50, 255]));
+        let origin = (monitor.work.x + 10, monitor.work.y + 10);
+        let mut toast = wayland.toast(frame, &monitor, Anchor::TopLeft, origin).unwrap();
+        toast.apply(Frame { opacity: 128, offset: (0, 0), scale: 1.0, time: 0.0 }, origin).unwrap();
+        toast.apply(Frame { opacity: 255, offset: (5, 0), scale: 0.5, time: 0.0 }, origin).unwrap();
+        for _ in 0..10 {
+            toast.apply(Frame { opacity: 255, offset: (0, 0), scale: 1.0, time: 0.0 }, origin).unwrap();
+        }
+        toast.queue.roundtrip(&mut toast.state).unwrap();
+        assert!(toast.state.configured && !toast.state.closed);
+        assert!(toast.buffers.len() <= 2);
+    }
+}
@@ -0,0 +1,222 @@
+//! Toasts as override-redirect ARGB windows on X11.
+//!
+//! The window manager never sees them, so they don't get decorated, focused or put in a taskbar, and an empty
+//! input shape lets clicks go straight through. Translucency needs a compositor; without one the toast is opaque.
+use crate::render::premultiply;
+use crate::anim::Frame;
+use crate::placement::{Monitor, Rect};
+use image::{imageops::{self, FilterType}, RgbaImage};
+use std::{io::{Error, ErrorKind}, result::Result};
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::shape::{ConnectionExt as _, SK, SO};
+use x11rb::protocol::xproto::*;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+/// A connection to the X server, for working out where a toast goes before it's made.
+pub struct X11 {
+    conn: RustConnection,
+    screen: usize,
+}
+
+impl X11 {
+    /// Connects to the display in `DISPLAY`.
+    pub fn connect() -> Result<Self, Error> {
+        Self::connect_to(None)
+    }
+
+    pub fn connect_to(display: Option<&str>) -> Result<Self, Error> {
+        let (conn, screen) = x11rb::connect(display).map_err(|e| Error::new(ErrorKind::NotConnected, e))?;
+        Ok(X11 { conn, screen })
+    }
+
+    fn root(&self) -> &Screen {
+        &self.conn.setup().roots[self.screen]
+    }
+
+    /// The scale `Xft.dpi` asks for, which is what desktops set for HiDPI. X has no per monitor DPI.
+    fn scale(&self) -> f32 {
+        let resources = self.conn.get_property(false, self.root().root, AtomEnum::RESOURCE_MANAGER, AtomEnum::STRING, 0, u32::MAX)
+            .ok().and_then(|cookie| cookie.reply().ok())
+            .map(|reply| String::from_utf8_lossy(&reply.value).into_owned())
+            .unwrap_or_default();
+        resources.lines()
+            .find_map(|line| line.strip_prefix("Xft.dpi:"))
+            .and_then(|dpi| dpi.trim().parse::<f32>().ok())
+            .map_or(1.0, |dpi| dpi / 96.0)
+    }
+
+    /// Every monitor RandR knows about, or the whole screen as one if it doesn't.
+    pub fn monitors(&self) -> Vec<Monitor> {
+        let scale = self.scale();
+        let root = self.root();
+        let monitors = self.conn.randr_get_monitors(root.root, true).ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .map(|reply| reply.monitors)
+            .unwrap_or_default();
+        if monitors.is_empty() {
+            return vec![Monitor { scale, ..Monitor::single((root.width_in_pixels as u32, root.height_in_pixels as u32)) }];
+        }
+        monitors.iter().map(|monitor| Monitor {
+            work: Rect { x: monitor.x as i32, y: monitor.y as i32, width: monitor.width as u32, height: monitor.height as u32 },
+            scale,
+            primary: monitor.primary,
+        }).collect()
+    }
+
+    pub fn cursor(&self) -> Option<(i32, i32)> {
+        let pointer = self.conn.query_pointer(self.root().root).ok()?.reply().ok()?;
+        Some((pointer.root_x as i32, pointer.root_y as i32))
+    }
+
+    /// Shows `frame` as a toast with its top left corner at `origin`, fully transparent until it gets `apply`ed.
+    pub fn toast(self, frame: RgbaImage, origin: (i32, i32)) -> Result<X11Toast, Error> {
+        X11Toast::new(self, frame, origin)
+    }
+}
+
+/// A toast window. Goes away when dropped.
+pub struct X11Toast {
+    conn: RustConnection,
+    window: Window,
+    gc: Gcontext,
+    colormap: Colormap,
+    frame: RgbaImage,
+    /// Whether the server wants pixels as BGRA rather than ARGB.
+    lsb_first: bool,
+}
+
+impl X11Toast {
+    fn new(x11: X11, frame: RgbaImage, (x, y): (i32, i32)) -> Result<Self, Error> {
+        let X11 { conn, screen } = x11;
+        let setup = conn.setup();
+        let root = &setup.roots[screen];
+        let visual = root.allowed_depths.iter()
+            .filter(|depth| depth.depth == 32)
+            .flat_map(|depth| &depth.visuals)
+            .find(|visual| visual.class == VisualClass::TRUE_COLOR)
+            .ok_or(Error::new(ErrorKind::Unsupported, "The X server has no 32 bit visual"))?
+            .visual_id;
+        let (root_window, lsb_first) = (root.root, setup.image_byte_order == ImageOrder::LSB_FIRST);
+
+        let colormap = conn.generate_id().map_err(Error::other)?;
+        conn.create_colormap(ColormapAlloc::NONE, colormap, root_window, visual).map_err(Error::other)?;
+        let window = conn.generate_id().map_err(Error::other)?;
+        let aux = CreateWindowAux::new()
+            .background_pixel(0)
+            .border_pixel(0)
+            .override_redirect(1)
+            .colormap(colormap)
+            .event_mask(EventMask::EXPOSURE);
+        conn.create_window(32, window, root_window, x as i16, y as i16, frame.width() as u16, frame.height() as u16, 0,
+            WindowClass::INPUT_OUTPUT, visual, &aux).map_err(Error::other)?;
+        let gc = conn.generate_id().map_err(Error::other)?;
+        conn.create_gc(gc, window, &CreateGCAux::new()).map_err(Error::other)?;
+
+        // For compositors and anything else that looks, even though the window manager won't.
+        let atom = |name: &[u8]| -> Result<Atom, Error> {
+            Ok(conn.intern_atom(false, name).map_err(Error::other)?.reply().map_err(Error::other)?.atom)
+        };
+        conn.change_property32(PropMode::REPLACE, window, atom(b"_NET_WM_WINDOW_TYPE")?, AtomEnum::ATOM,
+            &[atom(b"_NET_WM_WINDOW_TYPE_NOTIFICATION")?]).map_err(Error::other)?;
+        conn.change_property8(PropMode::REPLACE, window, AtomEnum::WM_NAME, AtomEnum::STRING, b"Song Spectre")
+            .map_err(Error::other)?;
+        // No input region at all, so clicks land on whatever's underneath.
+        conn.shape_rectangles(SO::SET, SK::INPUT, ClipOrdering::UNSORTED, window, 0, 0, &[]).map_err(Error::other)?;
+
+        conn.map_window(window).map_err(Error::other)?;
+        let toast = X11Toast { conn, window, gc, colormap, frame, lsb_first };
+        toast.draw(&toast.frame, 0)?;
+        Ok(toast)
+    }
+
+    /// Premultiplied pixels of `image` at `opacity`, the way the server wants them.
+    fn pixels(&self, image: &RgbaImage, opacity: u8) -> Vec<u8> {
+        let mut data = Vec::with_capacity(image.as_raw().len());
+        for pixel in image.pixels() {
+            let [r, g, b, a] = premultiply(pixel.0, opacity);
+            data.extend_from_slice(&if self.lsb_first { [b, g, r, a] } else { [a, r, g, b] });
+        }
+        data
+    }
+
+    /// Puts `image` in the window, a few rows at a time so no request is too big for the server.
+    fn draw(&self, image: &RgbaImage, opacity: u8) -> Result<(), Error> {
+        let data = self.pixels(image, opacity);
+        let row = image.width() as usize * 4;
+        let rows = ((self.conn.maximum_request_bytes() - 32) / row.max(1)).max(1);
+        for (i, chunk) in data.chunks(row * rows).enumerate() {
+            self.conn.put_image(ImageFormat::Z_PIXMAP, self.window, self.gc, image.width() as u16, (chunk.len() / row) as u16,
+                0, (i * rows) as i16, 0, 32, chunk).map_err(Error::other)?;
+        }
+        self.conn.flush().map_err(Error::other)
+    }
+
+    /// Swaps what the toast shows for `image`, the same size, from the next `apply` on.
+    pub fn set_image(&mut self, image: RgbaImage) {
+        self.frame = image;
+    }
+
+    /// Shows `frame`, with the toast's resting spot at `origin`.
+    pub fn apply(&mut self, frame: Frame, origin: (i32, i32)) -> Result<(), Error> {
+        let (width, height) = (self.frame.width() as f32, self.frame.height() as f32);
+        let scaled = ((width * frame.scale).round().max(1.0) as u32, (height * frame.scale).round().max(1.0) as u32);
+        let x = origin.0 + frame.offset.0 + ((width - scaled.0 as f32) / 2.0).round() as i32;
+        let y = origin.1 + frame.offset.1 + ((height - scaled.1 as f32) / 2.0).round() as i32;
+        let aux = ConfigureWindowAux::new().x(x).y(y).width(scaled.0).height(scaled.1).stack_mode(StackMode::ABOVE);
+        self.conn.configure_window(self.window, &aux).map_err(Error::other)?;
+        if scaled == self.frame.dimensions() {
+            self.draw(&self.frame, frame.opacity)
+        } else {
+            self.draw(&imageops::resize(&self.frame, scaled.0, scaled.1, FilterType::Triangle), frame.opacity)
+        }
+    }
+}
+
+impl Drop for X11Toast {
+    fn drop(&mut self) {
+        let _ = self.conn.free_gc(self.gc);
+        let _ = self.conn.destroy_window(self.window);
+        let _ = self.conn.free_colormap(self.colormap);
+        let _ = self.conn.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_display::TestDisplay;
+    use image::Rgba;
+
+    #[test]
+    #[ignore = "needs Xvfb, run with --ignored"]
+    fn shows_click_through_argb_toasts() {
+        let display = TestDisplay::start();
+        let x11 = X11::connect_to(Some(&display.name)).unwrap();
+        assert_eq!(x11.monitors()[0].work, Rect { x: 0, y: 0, width: 1280, height: 720 });
+
+        let mut frame = RgbaImage::from_pixel(40, 20, Rgba([200, 100, 50, 255]));
+        frame.put_pixel(0, 0, Rgba([200, 100, 50, 0]));
+        let mut toast = x11.toast(frame, (100, 50)).unwrap();
+        toast.apply(Frame { opacity: 128, offset: (10, 0), scale: 1.0, time: 0.0 }, (100, 50)).unwrap();
+
+        let conn = &toast.conn;
+        let attributes = conn.get_window_attributes(toast.window).unwrap().reply().unwrap();
+        assert!(attributes.override_redirect);
+        let geometry = conn.get_geometry(toast.window).unwrap().reply().unwrap();
+        assert_eq!((geometry.x, geometry.y, geometry.width, geometry.height, geometry.depth), (110, 50, 40, 20, 32));
+        let input = conn.shape_get_rectangles(toast.window, SK::INPUT).unwrap().reply().unwrap();
+        assert!(input.rectangles.is_empty());
+
+        let image = conn.get_image(ImageFormat::Z_PIXMAP, toast.window, 0, 0, 40, 20, !0).unwrap().reply().unwrap();
+        let pixel = |x: usize, y: usize| {
+            let i = (y * 40 + x) * 4;
+            let [b, g, r, a]: [u8; 4] = image.data[i..i + 4].try_into().unwrap();
+            if toast.lsb_first { [r, g, b, a] } else { [g, r, a, b] }
+        };
+        // Premultiplied, at half opacity.
+        assert_eq!(pixel(5, 5), [100, 50, 25, 128]);
+        assert_eq!(pixel(0, 0), [0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,117 @@
+//! Which monitor a toast goes on and where on it.
+//!
+//! All of it is plain geometry over a list of `Monitor`s, so every display backend places toasts the same way
+//! and only has to say what monitors there are and where the cursor and the player are.
+use crate::config::{MonitorPick, Position};
+
+/// A rectangle in desktop coordinates, which can go negative for monitors left of or above the primary one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+impl Rect {
+    pub fn contains(&self, (x, y): (i32, i32)) -> bool {
+        x >= self.x && y >= self.y && ((x - self.x) as u32) < self.width && ((y - self.y) as u32) < self.height
+    }
+
+    /// How many pixels this and `other` have in common.
+    pub fn overlap(&self, other: &Rect) -> u64 {
+        let span = |a: i32, a_len: u32, b: i32, b_len: u32| {
+            let start = a.max(b) as i64;
+            let end = (a as i64 + a_len as i64).min(b as i64 + b_len as i64);
+            (end - start).max(0) as u64
+        };
+        span(self.x, self.width, other.x, other.width) * span(self.y, self.height, other.y, other.height)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Monitor {
+    /// The part of the monitor toasts can go, without taskbars and docks.
+    pub work: Rect,
+    /// DPI scale, 1.0 being 96 DPI.
+    pub scale: f32,
+    pub primary: bool,
+}
+impl Monitor {
+    /// A lone `size` monitor, for when that's all there is to go on.
+    pub fn single(size: (u32, u32)) -> Self {
+        Monitor { work: Rect { x: 0, y: 0, width: size.0, height: size.1 }, scale: 1.0, primary: true }
+    }
+}
+
+/// What's known about where things are right now, for `MonitorPick`s that need it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Hints {
+    pub cursor: Option<(i32, i32)>,
+    /// The player's window.
+    pub player: Option<Rect>,
+}
+
+/// Picks the monitor `pick` asks for. Anything that can't be worked out from `hints` falls back to the primary
+/// monitor, and that to the first one. `None` only if there are no monitors at all.
+pub fn pick_monitor(monitors: &[Monitor], pick: MonitorPick, hints: &Hints) -> Option<Monitor> {
+    let primary = monitors.iter().find(|monitor| monitor.primary).or(monitors.first());
+    let picked = match pick {
+        MonitorPick::Primary => None,
+        MonitorPick::Cursor => hints.cursor.and_then(|cursor| monitors.iter().find(|monitor| monitor.work.contains(cursor))),
+        MonitorPick::Player => hints.player.and_then(|player| {
+            monitors.iter().filter(|monitor| monitor.work.overlap(&player) > 0).max_by_key(|monitor| monitor.work.overlap(&player))
+        }),
+    };
+    picked.or(primary).copied()
+}
+
+/// How much toasts on `monitor` get scaled up.
+pub fn scale(position: &Position, monitor: &Monitor) -> f32 {
+    if position.dpi_scale { monitor.scale } else { 1.0 }
+}
+
+/// Top left corner of a `size` toast on `monitor`. `size` is as drawn, so already scaled.
+pub fn place(position: &Position, monitor: &Monitor, size: (u32, u32)) -> (i32, i32) {
+    let scale = scale(position, monitor);
+    let margin = ((position.margin.0 as f32 * scale).round() as u32, (position.margin.1 as f32 * scale).round() as u32);
+    let (x, y) = Position { margin, ..position.clone() }.origin((monitor.work.width, monitor.work.height), size);
+    (monitor.work.x + x, monitor.work.y + y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitors() -> Vec<Monitor> {
+        vec![
+            Monitor { work: Rect { x: 0, y: 0, width: 1920, height: 1040 }, scale: 1.0, primary: true },
+            // A 4K monitor at 150% to the left.
+            Monitor { work: Rect { x: -3840, y: -200, width: 3840, height: 2160 }, scale: 1.5, primary: false },
+        ]
+    }
+
+    #[test]
+    fn picks_monitors() {
+        let monitors = monitors();
+        let pick = |pick, hints: Hints| pick_monitor(&monitors, pick, &hints).unwrap().work.x;
+        assert_eq!(pick(MonitorPick::Primary, Hints { cursor: Some((-10, 0)), player: None }), 0);
+        assert_eq!(pick(MonitorPick::Cursor, Hints { cursor: Some((-10, 0)), player: None }), -3840);
+        assert_eq!(pick(MonitorPick::Cursor, Hints { cursor: Some((5000, 0)), player: None }), 0);
+        // Mostly on the left one.
+        let player = Rect { x: -1000, y: 100, width: 1200, height: 800 };
+        assert_eq!(pick(MonitorPick::Player, Hints { cursor: None, player: Some(player) }), -3840);
+        assert_eq!(pick(MonitorPick::Player, Hints::default()), 0);
+        assert_eq!(pick_monitor(&[], MonitorPick::Primary, &Hints::default()), None);
+    }
+
+    #[test]
+    fn places_on_the_monitor() {
+        let monitors = monitors();
+        let position = Position::default();
+        assert_eq!(place(&position, &monitors[0], (480, 160)), (1920 - 480 - 24, 1040 - 160 - 24));
+        // Margins scale with the monitor, the size is already scaled.
+        assert_eq!(place(&position, &monitors[1], (720, 240)), (-720 - 36, -200 + 2160 - 240 - 36));
+        let unscaled = Position { dpi_scale: false, ..Position::default() };
+        assert_eq!(place(&unscaled, &monitors[1], (480, 160)), (-480 - 24, -200 + 2160 - 160 - 24));
+    }
+}
@@ -1,20 +1,35 @@
 pub mod img;
 pub use img::*;
-use windows::Foundation::IReference;
-pub use windows::Media::{self as WMedia, MediaPlaybackType as MPT, 
-    Control::{ 
-        GlobalSystemMediaTransportControlsSession as TCS, GlobalSystemMediaTransportControlsSessionMediaProperties as TCSProperties}};
+pub mod cache;
+#[cfg(target_os = "linux")]
+pub use cache::*;
+pub mod palette;
+pub use palette::*;
+pub mod serialize;
+#[allow(unused_imports)]
+pub use serialize::*;
+pub mod source;
+pub use source::*;
+#[cfg(windows)]
+use windows::{Foundation::IReference, Media::{MediaPlaybackAutoRepeatMode as MPARM, MediaPlaybackType as MPT}};
+#[cfg(windows)]
+use windows::Media::Control::GlobalSystemMediaTransportControlsSessionPlaybackStatus as TCSPlaybackStatus;
+#[cfg(windows)]
+use crate::media::tcs::{TCSProperties, TCS};
 use core::fmt;
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr, time::Duration};
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(PartialEq, Eq, Copy, Clone, Default)]
 pub struct SPT(pub u8);
+#[cfg_attr(not(windows), allow(dead_code))]
 impl SPT {
     pub const UNKNOWN: Self = Self(0);
     pub const AUDIO: Self = Self(1);
     pub const VIDEO: Self = Self(2);
     pub const IMAGE: Self = Self(3);
 }
+#[cfg(windows)]
 impl From<MPT> for SPT {
     fn from(mpt: MPT) -> Self {
         match mpt.0 {
@@ -26,6 +41,7 @@ impl From<MPT> for SPT {
         }
     }
 }
+#[cfg(windows)]
 impl From<IReference<MPT>> for SPT {
     fn from(mpt: IReference<MPT>) -> Self {
         SPT::from(mpt.Value().unwrap())
@@ -44,6 +60,20 @@ impl Display for  SPT{
     }
 }
 
+impl FromStr for SPT {
+    type Err = String;
+    /// Parses the names `Display` gives, case insensitively.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "UNKNOWN" => Ok(SPT::UNKNOWN),
+            "AUDIO" => Ok(SPT::AUDIO),
+            "VIDEO" => Ok(SPT::VIDEO),
+            "IMAGE" => Ok(SPT::IMAGE),
+            _ => Err(format!("Unknown playback type: {}", s)),
+        }
+    }
+}
+
 // Implement Debug for SpectrePlayType
 impl fmt::Debug for SPT {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -51,6 +81,102 @@ impl fmt::Debug for SPT {
     }
 }
 
+/// Whether a session is playing or not.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub enum PlaybackStatus {
+    /// The source doesn't say. Treated like `Playing`, so sources that can't tell still get toasts.
+    #[default]
+    Unknown,
+    Playing,
+    Paused,
+    Stopped,
+}
+impl PlaybackStatus {
+    /// Whether a session with this status is worth a toast.
+    pub fn is_active(&self) -> bool {
+        matches!(self, PlaybackStatus::Playing | PlaybackStatus::Unknown)
+    }
+}
+#[cfg(windows)]
+impl From<TCSPlaybackStatus> for PlaybackStatus {
+    fn from(status: TCSPlaybackStatus) -> Self {
+        match status {
+            TCSPlaybackStatus::Playing => PlaybackStatus::Playing,
+            TCSPlaybackStatus::Paused => PlaybackStatus::Paused,
+            // Closed and Opened sessions aren't playing anything yet either.
+            TCSPlaybackStatus::Stopped | TCSPlaybackStatus::Closed | TCSPlaybackStatus::Opened => PlaybackStatus::Stopped,
+            _ => PlaybackStatus::Unknown,
+        }
+    }
+}
+impl Display for PlaybackStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlaybackStatus::Unknown => write!(f, "Unknown"),
+            PlaybackStatus::Playing => write!(f, "Playing"),
+            PlaybackStatus::Paused => write!(f, "Paused"),
+            PlaybackStatus::Stopped => write!(f, "Stopped"),
+        }
+    }
+}
+impl FromStr for PlaybackStatus {
+    type Err = String;
+    /// Parses the names `Display` gives, case insensitively. These are also MPRIS's `PlaybackStatus` values.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "unknown" => Ok(PlaybackStatus::Unknown),
+            "playing" => Ok(PlaybackStatus::Playing),
+            "paused" => Ok(PlaybackStatus::Paused),
+            "stopped" => Ok(PlaybackStatus::Stopped),
+            _ => Err(format!("Unknown playback status: {}", s)),
+        }
+    }
+}
+
+/// What a session does when it gets to the end of the track.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub enum RepeatMode {
+    #[default]
+    None,
+    Track,
+    List,
+}
+#[cfg(windows)]
+impl From<MPARM> for RepeatMode {
+    fn from(mode: MPARM) -> Self {
+        match mode {
+            MPARM::Track => RepeatMode::Track,
+            MPARM::List => RepeatMode::List,
+            _ => RepeatMode::None,
+        }
+    }
+}
+impl Display for RepeatMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepeatMode::None => write!(f, "None"),
+            RepeatMode::Track => write!(f, "Track"),
+            RepeatMode::List => write!(f, "List"),
+        }
+    }
+}
+impl FromStr for RepeatMode {
+    type Err = String;
+    /// Parses the names `Display` gives, case insensitively, and MPRIS's `LoopStatus` values.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(RepeatMode::None),
+            "track" => Ok(RepeatMode::Track),
+            "list" | "playlist" => Ok(RepeatMode::List),
+            _ => Err(format!("Unknown repeat mode: {}", s)),
+        }
+    }
+}
+
+pub const UNKNOWN_TITLE: &str = "Unknown Title";
+pub const UNKNOWN_ARTIST: &str = "Unknown Artist";
+pub const UNKNOWN_ALBUM: &str = "Unknown Album";
+
 /// `SpectreProps` is a struct that holds all of the media metadata found in a 'TCSProperties', but in a more rusty way.
 ///
 /// The `new()` and `new_async()` methods can be used to create new instances of the `SpectreProps` struct, while the `sync()` method can be used to update the properties of an existing instance based on the provided `TCSProperties`.
@@ -66,15 +192,24 @@ pub struct SpectreProps {
     pub track_count: Option<i32>,
     pub playback_type: SPT,
     pub subtitle: Option<String>,
+    pub status: PlaybackStatus,
+    /// How far into the track the session was when the props were read.
+    pub position: Option<Duration>,
+    /// Length of the track.
+    pub duration: Option<Duration>,
+    pub shuffle: Option<bool>,
+    pub repeat: Option<RepeatMode>,
+    /// The app the session belongs to.
+    pub source: SourceApp,
 }
 
 
 impl Default for SpectreProps {
     fn default() -> Self {
         SpectreProps {
-            title: "Unknown Title".to_string(),
-            artist: "Unknown Artist".to_string(),
-            album: "Unknown Album".to_string(),
+            title: UNKNOWN_TITLE.to_string(),
+            artist: UNKNOWN_ARTIST.to_string(),
+            album: UNKNOWN_ALBUM.to_string(),
             album_artist: None,
             genres: vec![],
             thumbnail: ERROR_THUMB.clone(),
@@ -82,6 +217,12 @@ impl Default for SpectreProps {
             track_count: None,
             playback_type: SPT::UNKNOWN,
             subtitle: None,
+            status: PlaybackStatus::Unknown,
+            position: None,
+            duration: None,
+            shuffle: None,
+            repeat: None,
+            source: SourceApp::default(),
         }
     }
 }
@@ -96,12 +237,14 @@ impl SpectreProps {
         Self::default()
     }
 
+    #[cfg(windows)]
     pub fn from_tcsp(props: TCSProperties) -> Self {
         let mut spectre_props = Self::new();
         spectre_props.sync(props);
         spectre_props
     }
 
+    #[cfg(windows)]
     pub fn sync(&mut self, properties: TCSProperties) {
         self.title = match properties.Title() {
             Ok(title) => {if title.is_empty(){
@@ -154,6 +297,62 @@ impl SpectreProps {
         };
 
     }
+
+    /// Updates the playback state and timeline from the `TCS` itself, which is where Windows keeps those rather than in its `TCSProperties`.
+    #[cfg(windows)]
+    pub fn sync_playback(&mut self, sesh: &TCS) {
+        match sesh.GetPlaybackInfo() {
+            Ok(info) => {
+                self.status = info.PlaybackStatus().map(PlaybackStatus::from).unwrap_or_default();
+                self.shuffle = info.IsShuffleActive().and_then(|s| s.Value()).ok();
+                self.repeat = info.AutoRepeatMode().and_then(|r| r.Value()).ok().map(RepeatMode::from);
+            }
+            Err(_) => {
+                self.status = PlaybackStatus::Unknown;
+                self.shuffle = None;
+                self.repeat = None;
+            }
+        }
+        // Timeline times can be negative, which `Duration` can't be, so anything before the start is just the start.
+        let span = |t: windows::Foundation::TimeSpan| Duration::from_nanos((t.Duration.max(0) as u64) * 100);
+        match sesh.GetTimelineProperties() {
+            Ok(timeline) => {
+                let start = timeline.StartTime().map(span).unwrap_or_default();
+                self.position = timeline.Position().ok().map(|p| span(p).saturating_sub(start));
+                self.duration = timeline.EndTime().ok().map(|e| span(e).saturating_sub(start)).filter(|d| !d.is_zero());
+            }
+            Err(_) => {
+                self.position = None;
+                self.duration = None;
+            }
+        }
+    }
+}
+
+impl SpectreProps {
+    /// Whether `other` is the same track as this, ignoring things that can change mid-song like the thumbnail.
+    pub fn same_track(&self, other: &SpectreProps) -> bool {
+        self.title == other.title
+            && self.artist == other.artist
+            && self.album == other.album
+            && self.track_number == other.track_number
+            && self.subtitle == other.subtitle
+    }
+
+    /// The colors of the thumbnail, see `Palette::of`.
+    pub fn palette(&self) -> Palette {
+        Palette::of(&self.thumbnail)
+    }
+}
+
+/// Formats a time as `m:ss`, or `h:mm:ss` once it's an hour or more.
+pub fn fmt_time(time: Duration) -> String {
+    let secs = time.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
 }
 
 impl fmt::Display for SpectreProps {
@@ -162,7 +361,15 @@ impl fmt::Display for SpectreProps {
         writeln!(f, "Artist: {}", &self.artist)?;
         writeln!(f, "Album: {}", &self.album)?;
         writeln!(f, "Album Artist: {}", &self.album_artist.as_deref().unwrap_or(""))?;
-        writeln!(f, "Genres: {}", &self.genres.join(", "))
+        writeln!(f, "Genres: {}", &self.genres.join(", "))?;
+        if !self.source.name.is_empty() {
+            writeln!(f, "Source: {} ({})", &self.source.name, &self.source.id)?;
+        }
+        write!(f, "Status: {}", &self.status)?;
+        if let (Some(position), Some(duration)) = (self.position, self.duration) {
+            write!(f, " ({}/{})", fmt_time(position), fmt_time(duration))?;
+        }
+        writeln!(f)
     }
 }
 
@@ -179,6 +386,12 @@ impl fmt::Debug for SpectreProps {
             .field("track_count", &self.track_count.unwrap_or_default())
             .field("playback_type", &self.playback_type)
             .field("subtitle", &self.subtitle.as_deref().unwrap_or_default())
+            .field("status", &self.status)
+            .field("position", &self.position)
+            .field("duration", &self.duration)
+            .field("shuffle", &self.shuffle)
+            .field("repeat", &self.repeat)
+            .field("source", &self.source)
             .finish()
     }
 }
\ No newline at end of file
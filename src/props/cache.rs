@@ -0,0 +1,139 @@
+//! Thumbnails that were already made, so the same art doesn't get decoded and resized on every sync.
+//!
+//! Thumbnails are keyed by a hash of the art's raw bytes and the size they're fitted to. The most recently used
+//! `[cache] memory` of them stay in memory. With `disk = true` they're also saved as PNGs in the user's cache dir,
+//! so they last between runs.
+use super::img::*;
+use crate::config::{self, Cache};
+use indexmap::IndexMap;
+use sha2::{Digest, Sha256};
+use std::{fs, path::PathBuf, sync::{LazyLock, Mutex}};
+
+static THUMBS: LazyLock<Mutex<ThumbCache>> = LazyLock::new(|| Mutex::new(ThumbCache::new(0, None)));
+
+/// The thumbnail for encoded art `data`, at the config's thumbnail size. `ERROR_THUMB` if it won't decode.
+#[cfg_attr(not(any(windows, target_os = "linux")), allow(dead_code))]
+pub fn thumb_from_bytes(data: &[u8]) -> DynamicImage {
+    let config = config::get();
+    let mut thumbs = THUMBS.lock().unwrap();
+    thumbs.configure(&config.cache);
+    thumbs.get(data, config.size.thumb).unwrap_or_else(|| ERROR_THUMB.clone())
+}
+
+/// Hex SHA-256 of the art's bytes and the size it gets fitted to.
+fn key(data: &[u8], size: u32) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(size.to_le_bytes());
+    hasher.update(data);
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub struct ThumbCache {
+    /// Least recently used first.
+    thumbs: IndexMap<String, DynamicImage>,
+    capacity: usize,
+    dir: Option<PathBuf>,
+}
+
+impl ThumbCache {
+    /// Keeps up to `capacity` thumbnails in memory, and every one in `dir` if there is one.
+    pub fn new(capacity: usize, dir: Option<PathBuf>) -> Self {
+        ThumbCache { thumbs: IndexMap::new(), capacity, dir }
+    }
+
+    /// Picks up the `[cache]` settings.
+    fn configure(&mut self, cache: &Cache) {
+        self.capacity = cache.memory;
+        self.dir = if cache.disk { Cache::dir() } else { None };
+        self.trim();
+    }
+
+    /// The thumbnail for `data` fitted to a `size` square, made only if it isn't cached. `None` if it won't decode.
+    pub fn get(&mut self, data: &[u8], size: u32) -> Option<DynamicImage> {
+        let key = key(data, size);
+        if let Some(thumb) = self.thumbs.shift_remove(&key) {
+            self.thumbs.insert(key, thumb.clone());
+            return Some(thumb);
+        }
+        let thumb = match self.load(&key) {
+            Some(thumb) => thumb,
+            None => {
+                let thumb = fit_thumb_to(image::load_from_memory(data).ok()?, size);
+                self.save(&key, &thumb);
+                thumb
+            }
+        };
+        self.thumbs.insert(key, thumb.clone());
+        self.trim();
+        Some(thumb)
+    }
+
+    fn trim(&mut self) {
+        while self.thumbs.len() > self.capacity {
+            self.thumbs.shift_remove_index(0);
+        }
+    }
+
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(key).with_extension("png"))
+    }
+
+    fn load(&self, key: &str) -> Option<DynamicImage> {
+        image::open(self.path(key)?).ok()
+    }
+
+    /// Saves to disk if there's a dir to save to. Failing to is no worse than not caching, so errors are ignored.
+    fn save(&self, key: &str, thumb: &DynamicImage) {
+        let (Some(dir), Some(path)) = (&self.dir, self.path(key)) else { return };
+        // Written whole then moved into place, so another instance never reads half a file.
+        let partial = path.with_extension("png.part");
+        let _ = fs::create_dir_all(dir)
+            .map_err(image::ImageError::IoError)
+            .and_then(|_| thumb.save_with_format(&partial, image::ImageFormat::Png))
+            .and_then(|_| fs::rename(&partial, &path).map_err(image::ImageError::IoError));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+    use std::io::Cursor;
+
+    /// `color` as PNG bytes, the way a player hands art over.
+    fn art(color: [u8; 4]) -> Vec<u8> {
+        let mut data = Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(64, 64, Rgba(color)))
+            .write_to(&mut data, image::ImageFormat::Png).unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn keeps_the_most_recently_used() {
+        let mut cache = ThumbCache::new(2, None);
+        let (a, b, c) = (art([255, 0, 0, 255]), art([0, 255, 0, 255]), art([0, 0, 255, 255]));
+        let thumb = cache.get(&a, 32).unwrap();
+        assert_eq!(thumb.to_rgba8().dimensions(), (32, 32));
+        cache.get(&b, 32);
+        cache.get(&a, 32);
+        cache.get(&c, 32);
+        assert_eq!(cache.thumbs.keys().collect::<Vec<_>>(), [&key(&a, 32), &key(&c, 32)]);
+        // Another size is another thumbnail.
+        assert_eq!(cache.get(&a, 16).unwrap().width(), 16);
+        assert_eq!(cache.get(b"not an image", 32), None);
+    }
+
+    #[test]
+    fn keeps_thumbnails_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = art([255, 0, 0, 255]);
+        ThumbCache::new(0, Some(dir.path().to_path_buf())).get(&data, 32).unwrap();
+        let path = dir.path().join(format!("{}.png", key(&data, 32)));
+        assert!(path.exists());
+
+        // Whatever's on disk is what comes back, without decoding the art again.
+        RgbaImage::from_pixel(32, 32, Rgba([1, 2, 3, 255])).save(&path).unwrap();
+        let thumb = ThumbCache::new(1, Some(dir.path().to_path_buf())).get(&data, 32).unwrap();
+        assert_eq!(thumb.to_rgba8().get_pixel(0, 0), &Rgba([1, 2, 3, 255]));
+    }
+}
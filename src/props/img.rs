@@ -1,10 +1,9 @@
-use std::sync::LazyLock;
+use image::{Rgba, RgbaImage};
+use serde::Deserialize;
+use std::{str::FromStr, sync::LazyLock};
 #[allow(unused_imports)]
 use crate::utils::*;
-use windows::Win32::{Foundation::GetLastError, 
-    Graphics::Gdi::{
-        CreateDIBitmap, BITMAPINFOHEADER, BI_RGB, CBM_INIT, RGBQUAD}};
-use image::GenericImageView;
+#[cfg(windows)]
 pub(crate) use windows::Win32::Graphics::Gdi::{
     HBITMAP, HDC, BITMAP, BITMAPINFO, DIB_RGB_COLORS};
 
@@ -18,25 +17,42 @@ pub(crate) static ERROR_THUMB: LazyLock<DynamicImage> = LazyLock::new(|| {
 
 pub(crate) use img_traits::*;
 mod img_traits{
+#[cfg(windows)]
 pub(crate) use windows::Storage::Streams::IRandomAccessStreamReference as StreamRef;
 pub(crate) use image::imageops::FilterType;
 pub(crate) use image::DynamicImage;
+    #[cfg(windows)]
     use std::io::{Error, ErrorKind}; 
+    #[cfg(windows)]
     use windows::Storage::Streams::DataReader;
-    use image::{ GenericImage, ImageResult, ImageError};
-    use super::ERROR_THUMB;
+    use image::{GenericImage, Rgba};
+    #[cfg(windows)]
+    use image::{ImageResult, ImageError};
+    use super::{cut_circle, round_corners, shadowed, ArtShape, ERROR_THUMB};
 
     
+    #[cfg(windows)]
     trait WinToImgErrExt<T> { fn map_err_img(self) -> Result<T, ImageError>; }
 
+    #[cfg(windows)]
     impl<T> WinToImgErrExt<T> for windows::core::Result<T> {
         fn map_err_img(self) -> Result<T, ImageError> {
             self.map_err(|e| ImageError::IoError(e.into()))
     }} 
 
-    pub(super) trait ImgExt {
+    pub(crate) trait ImgExt {
         fn resize_centered(&self, nwidth: u32, nheight: u32, filter: FilterType) -> Self;
-        fn from_stream_ref(reference: Option<StreamRef>) -> ImageResult<DynamicImage>;
+        /// The same image with its corners rounded off to `radius`. Always RGBA, like the rest of these.
+        fn rounded(&self, radius: u32) -> Self;
+        /// Cut down to the biggest circle that fits, in the middle.
+        fn circle(&self) -> Self;
+        /// `circle`, with a hole in the middle like a record has.
+        fn vinyl(&self) -> Self;
+        /// Cut into `shape`, with corners rounded to `radius` if it's `Square`.
+        fn shaped(&self, shape: ArtShape, radius: u32) -> Self;
+        /// The image over a soft `color` shadow that falls a little below it. `spread` pixels bigger on every side,
+        /// to make room for the shadow.
+        fn drop_shadow(&self, spread: u32, color: Rgba<u8>) -> Self;
     }
 
     impl ImgExt for DynamicImage {
@@ -54,94 +70,212 @@ pub(crate) use image::DynamicImage;
             }
             output_image
         }
-        fn from_stream_ref(reference: Option<StreamRef>) -> ImageResult<DynamicImage> {
-            let stream = reference.ok_or(ImageError::IoError(Error::new(ErrorKind::InvalidInput, "No Stream")))?.OpenReadAsync().map_err_img()?.get().map_err_img()?; 
-            let stream_len = stream.Size().map_err_img()?;
-            let mut img_data = vec![0u8; stream_len as usize];
-            let reader = DataReader::CreateDataReader(&stream).map_err_img()?; 
-            reader.LoadAsync(stream_len as u32).map_err_img()?.get().map_err_img()?;
-            reader.ReadBytes(&mut img_data).map_err_img()?;
-            let _ = reader.Close();
-            let img = image::load_from_memory(&img_data)?;
-            Ok(img)
+
+        fn rounded(&self, radius: u32) -> Self {
+            let mut image = self.to_rgba8();
+            round_corners(&mut image, radius);
+            image.into()
+        }
+
+        fn circle(&self) -> Self {
+            let mut image = self.to_rgba8();
+            cut_circle(&mut image, 0.0);
+            image.into()
         }
+
+        fn vinyl(&self) -> Self {
+            let mut image = self.to_rgba8();
+            cut_circle(&mut image, VINYL_HOLE);
+            image.into()
+        }
+
+        fn shaped(&self, shape: ArtShape, radius: u32) -> Self {
+            match shape {
+                ArtShape::Square if radius == 0 => self.clone(),
+                ArtShape::Square => self.rounded(radius),
+                ArtShape::Circle => self.circle(),
+                ArtShape::Vinyl => self.vinyl(),
+            }
+        }
+
+        fn drop_shadow(&self, spread: u32, color: Rgba<u8>) -> Self {
+            shadowed(&self.to_rgba8(), spread, color).into()
+        }
+    }
+
+    /// How big a record's hole is, as a fraction of the record.
+    const VINYL_HOLE: f32 = 0.12;
+
+    /// Reads everything in the stream behind `reference`, still encoded.
+    #[cfg(windows)]
+    pub(crate) fn read_stream_ref(reference: Option<StreamRef>) -> ImageResult<Vec<u8>> {
+        let stream = reference.ok_or(ImageError::IoError(Error::new(ErrorKind::InvalidInput, "No Stream")))?.OpenReadAsync().map_err_img()?.get().map_err_img()?; 
+        let stream_len = stream.Size().map_err_img()?;
+        let mut img_data = vec![0u8; stream_len as usize];
+        let reader = DataReader::CreateDataReader(&stream).map_err_img()?; 
+        reader.LoadAsync(stream_len as u32).map_err_img()?.get().map_err_img()?;
+        reader.ReadBytes(&mut img_data).map_err_img()?;
+        let _ = reader.Close();
+        Ok(img_data)
     }
 } 
 
 
 
 
-/// Creates a thumbnail image from a stream reference. If the stream reference is `None` or an error occurs, a default pink image is returned.
+/// Fits an image into the square thumbnail box from the config (`THUMB_W` x `THUMB_H` by default), leaving it alone if it already fits exactly.
+pub fn fit_thumb(img: DynamicImage) -> DynamicImage {
+    fit_thumb_to(img, crate::config::get().size.thumb)
+}
+
+/// Fits an image into a `size` square, leaving it alone if it already fits exactly.
+pub fn fit_thumb_to(img: DynamicImage, size: u32) -> DynamicImage {
+    if img.height() != size || img.width() != size { 
+        img.resize_centered(size, size, FilterType::Lanczos3)
+    } else { img }
+}
+
+/// What shape album art gets cut into, see `ImgExt::shaped`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtShape {
+    /// As it is, maybe with its corners rounded.
+    #[default]
+    Square,
+    Circle,
+    /// A circle with a hole in the middle, like a record.
+    Vinyl,
+}
+impl FromStr for ArtShape {
+    type Err = String;
+    /// Parses `square`, `circle` or `vinyl`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "square" => Ok(ArtShape::Square),
+            "circle" => Ok(ArtShape::Circle),
+            "vinyl" => Ok(ArtShape::Vinyl),
+            _ => Err(format!("Unknown art shape: {} (expected square, circle or vinyl)", s)),
+        }
+    }
+}
+
+/// Multiplies the alpha of every pixel by how much of it `coverage` says is kept, from 0 to 1, given the pixel's center.
+fn mask(image: &mut RgbaImage, coverage: impl Fn(f32, f32) -> f32) {
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let kept = coverage(x as f32 + 0.5, y as f32 + 0.5);
+        if kept < 1.0 {
+            pixel.0[3] = (pixel.0[3] as f32 * kept.max(0.0)).round() as u8;
+        }
+    }
+}
+
+/// Rounds the corners of `image` off to `radius`, fading the pixels along the curve so it's smooth.
+pub(crate) fn round_corners(image: &mut RgbaImage, radius: u32) {
+    let size = image.dimensions();
+    round_corners_of_part(image, (0, 0), size, radius);
+}
+
+/// `round_corners` for the part of a `size` image that `part` is, with its top left corner at `at`.
+pub(crate) fn round_corners_of_part(part: &mut RgbaImage, at: (u32, u32), size: (u32, u32), radius: u32) {
+    let (width, height) = (size.0 as f32, size.1 as f32);
+    let r = (radius as f32).min(width / 2.0).min(height / 2.0);
+    mask(part, |x, y| {
+        let (x, y) = (x + at.0 as f32, y + at.1 as f32);
+        // How far into the corner's square, from the center of the circle it's rounded to.
+        let dx = (r - x).max(x - (width - r)).max(0.0);
+        let dy = (r - y).max(y - (height - r)).max(0.0);
+        if dx == 0.0 || dy == 0.0 {
+            return 1.0;
+        }
+        (r - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0.0, 1.0)
+    });
+}
+
+/// Cuts `image` down to the biggest circle in its middle, with a `hole` in the middle of that as a fraction of its size.
+pub(crate) fn cut_circle(image: &mut RgbaImage, hole: f32) {
+    let (cx, cy) = (image.width() as f32 / 2.0, image.height() as f32 / 2.0);
+    let r = cx.min(cy);
+    let hole = r * hole;
+    mask(image, |x, y| {
+        let d = ((x - cx).powi(2) + (y - cy).powi(2)).sqrt();
+        (r - d + 0.5).clamp(0.0, 1.0) * (d - hole + 0.5).clamp(0.0, 1.0)
+    });
+}
+
+/// `image` in the middle of a canvas `spread` pixels bigger all round, over a soft `color` shadow of itself that
+/// falls a little below it.
+pub(crate) fn shadowed(image: &RgbaImage, spread: u32, color: Rgba<u8>) -> RgbaImage {
+    let mut canvas = shadow_of(image, spread, color);
+    image::imageops::overlay(&mut canvas, image, spread as i64, spread as i64);
+    canvas
+}
+
+/// Just the shadow `shadowed` puts under `image`.
+pub(crate) fn shadow_of(image: &RgbaImage, spread: u32, color: Rgba<u8>) -> RgbaImage {
+    let [r, g, b, a] = color.0;
+    let (width, height) = (image.width() + spread * 2, image.height() + spread * 2);
+    // Transparent shadow color rather than transparent black, so the edges don't blur towards black.
+    let mut shadow = RgbaImage::from_pixel(width, height, Rgba([r, g, b, 0]));
+    let drop = spread / 4;
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let (sx, sy) = (x + spread, y + spread + drop);
+        if sy < height {
+            shadow.get_pixel_mut(sx, sy).0[3] = (a as u32 * pixel.0[3] as u32 / 255) as u8;
+        }
+    }
+    if spread > 0 { image::imageops::blur(&shadow, spread as f32 / 3.0) } else { shadow }
+}
+
+/// Creates a thumbnail image from a stream reference, through the thumbnail cache. If the stream reference is `None` or an error occurs, a default pink image is returned.
 ///
 /// # Arguments
 /// * `reference` - An optional `StreamRef` that contains the image data.
 ///
 /// # Returns
 /// A `DynamicImage` containing the thumbnail image, Or a placeholder image if something goes wrong.
+#[cfg(windows)]
 pub fn ref_to_thumb(reference: Option<StreamRef>) -> DynamicImage {
-    match DynamicImage::from_stream_ref(reference) {
-        Ok(mut img) => { 
-            if img.height() != THUMB_H || img.width() != THUMB_W { 
-                img = img.resize_centered(THUMB_W, THUMB_H, FilterType::Lanczos3); 
-            } img 
-        },
+    match read_stream_ref(reference) {
+        Ok(data) => super::cache::thumb_from_bytes(&data),
         Err(_) => ERROR_THUMB.clone(),
     }
 }
 
-// Function to convert DynamicImage to a GDI bitmap
-pub fn dynamic_image_to_bitmap(hdc: HDC, image: &DynamicImage) -> Result<HBITMAP, String> {
-    let (width, height) = (image.dimensions());
-    let image_data = image.to_rgba8(); // Convert to RGBA format
-    
-    // Prepare bitmap info header
-    let mut bmi_h: BITMAPINFOHEADER = BITMAPINFOHEADER::default();
-    bmi_h.biSize = std::mem::size_of::<BITMAPINFOHEADER>() as u32;
-    bmi_h.biWidth = width as i32;
-    bmi_h.biHeight = -(height as i32); // Negative to indicate a top-down DIB
-    bmi_h.biPlanes = 1;
-    bmi_h.biBitCount = 32; // 32 bits for RGBA
-    bmi_h.biCompression = BI_RGB.0;
-
-    let bmi = Box::new(BITMAPINFO {
-        bmiHeader: bmi_h,
-        bmiColors: [RGBQUAD::default(); 1],
-    });
-    //let mut pb: *mut std::ffi::c_void = std::ptr::null_mut();
-
-    let image_data_ptr: *const std::ffi::c_void = image_data.as_ptr() as *const std::ffi::c_void;
-    let h_bitmap = unsafe {
-        CreateDIBitmap(
-            hdc,
-            Some(&bmi.bmiHeader),
-            CBM_INIT as u32,
-            Some(image_data_ptr), // Use the padded image data
-            Some(bmi.as_ref()), // Full bitmap info (including header)
-            DIB_RGB_COLORS // Use RGB color data
-        )
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    
-    #[cfg(debug_assertions)]
-
-    match debug::check_hbitmap(h_bitmap, *bmi, hdc, width, height, 32) {
-       Ok(k) => print!(""),
-       Err(e) => return Err(e),        
-    };
-
-    /* 
-    match debug::check_hbitmap(h_bitmap, *bmi, hdc, width, height, 32) {
-       Ok(k) => println!("{}", k),
-       Err(e) => return Err(e),        
-    };
-    */
-    
-    
-    if h_bitmap.is_invalid() {
-        // Get the last error if the bitmap creation failed
-        let error_code = unsafe { GetLastError() };
-        return Err(format!("Failed to create bitmap. Error code: {}", error_code.0));
+    fn alpha(image: &DynamicImage, x: u32, y: u32) -> u8 {
+        image.to_rgba8().get_pixel(x, y).0[3]
+    }
+
+    #[test]
+    fn masks() {
+        let square = DynamicImage::ImageRgba8(RgbaImage::from_pixel(20, 20, Rgba([200, 30, 30, 255])));
+        let rounded = square.rounded(6);
+        assert_eq!((alpha(&rounded, 0, 0), alpha(&rounded, 10, 0), alpha(&rounded, 10, 10)), (0, 255, 255));
+        // Antialiased, so the curve isn't all or nothing.
+        assert!((1..255).contains(&alpha(&rounded, 1, 2)), "{}", alpha(&rounded, 1, 2));
+        assert_eq!(square.shaped(ArtShape::Square, 0), square);
+
+        let circle = square.circle();
+        assert_eq!((alpha(&circle, 0, 0), alpha(&circle, 10, 10), alpha(&circle, 10, 1)), (0, 255, 255));
+        let vinyl = square.resize_exact(50, 50, FilterType::Nearest).vinyl();
+        assert_eq!((alpha(&vinyl, 2, 2), alpha(&vinyl, 25, 25), alpha(&vinyl, 25, 10)), (0, 0, 255));
+        assert_eq!(vinyl.to_rgba8().get_pixel(25, 10), &Rgba([200, 30, 30, 255]));
+        assert_eq!("vinyl".parse(), Ok(ArtShape::Vinyl));
+        assert!("hexagon".parse::<ArtShape>().is_err());
     }
-    Ok(h_bitmap)
 
-}
\ No newline at end of file
+    #[test]
+    fn drop_shadows() {
+        let art = DynamicImage::ImageRgba8(RgbaImage::from_pixel(12, 12, Rgba([200, 30, 30, 255])));
+        let shadowed = art.drop_shadow(6, Rgba([0, 0, 0, 200]));
+        assert_eq!((shadowed.width(), shadowed.height()), (24, 24));
+        assert_eq!(shadowed.to_rgba8().get_pixel(12, 12), &Rgba([200, 30, 30, 255]));
+        // Soft all round, and more of it below than above.
+        let (below, above) = (alpha(&shadowed, 12, 20), alpha(&shadowed, 12, 3));
+        assert!(below > above && above > 0 && below < 200, "{} {}", below, above);
+        assert_eq!(alpha(&shadowed, 0, 0), 0);
+    }
+}
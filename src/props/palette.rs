@@ -0,0 +1,179 @@
+//! Colors picked out of album art, for toasts that match the album they're showing.
+//!
+//! `Palette::of` buckets the thumbnail's colors and picks a dominant, a vibrant and a muted one, plus a text color
+//! that's readable on the dominant one. Readability is WCAG's contrast ratio, see `contrast`.
+use super::img::*;
+use image::{Rgba, RgbaImage};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// WCAG's minimum contrast for normal sized text.
+pub const MIN_CONTRAST: f32 = 4.5;
+
+const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+/// What the toast was before it had any other color, for art with no colors to pick.
+const GREY: Rgba<u8> = Rgba([126, 126, 126, 255]);
+
+/// Which of the art's colors a toast gets tinted with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tint {
+    /// The theme's own colors.
+    #[default]
+    None,
+    Dominant,
+    Vibrant,
+    Muted,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    /// The color most of the art is.
+    pub dominant: Rgba<u8>,
+    /// The most colorful one, or `dominant` if nothing is.
+    pub vibrant: Rgba<u8>,
+    /// The most common greyish one, or `dominant` if there's none.
+    pub muted: Rgba<u8>,
+    /// Black or white, whichever reads better on `dominant`.
+    pub text: Rgba<u8>,
+}
+impl Default for Palette {
+    fn default() -> Self {
+        Palette { dominant: GREY, vibrant: GREY, muted: GREY, text: text_on(GREY) }
+    }
+}
+
+/// Colors close enough to share a bucket, averaged, and how many pixels they cover.
+struct Swatch {
+    color: [u8; 3],
+    count: u32,
+}
+impl Swatch {
+    /// Hue-less saturation and lightness, both 0 to 1.
+    fn saturation_lightness(&self) -> (f32, f32) {
+        let [r, g, b] = self.color.map(|c| c as f32 / 255.0);
+        let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+        let lightness = (max + min) / 2.0;
+        let saturation = if max == min { 0.0 } else { (max - min) / (1.0 - (2.0 * lightness - 1.0).abs()) };
+        (saturation, lightness)
+    }
+
+    fn rgba(&self) -> Rgba<u8> {
+        let [r, g, b] = self.color;
+        Rgba([r, g, b, 255])
+    }
+}
+
+impl Palette {
+    /// Picks the colors out of `image`. Transparent pixels, like the borders `fit_thumb` leaves, don't count.
+    pub fn of(image: &DynamicImage) -> Palette {
+        // Every pixel of a full thumbnail is more than it takes to tell what color it is.
+        let small: RgbaImage = image.resize_exact(32, 32, FilterType::Triangle).to_rgba8();
+        // 4 bits a channel, sorted so ties always go the same way.
+        let mut buckets: BTreeMap<u16, (u32, [u32; 3])> = BTreeMap::new();
+        for Rgba([r, g, b, a]) in small.pixels().copied() {
+            if a < 128 {
+                continue;
+            }
+            let key = (r as u16 >> 4) << 8 | (g as u16 >> 4) << 4 | b as u16 >> 4;
+            let (count, sum) = buckets.entry(key).or_default();
+            *count += 1;
+            for (sum, c) in sum.iter_mut().zip([r, g, b]) {
+                *sum += c as u32;
+            }
+        }
+        let swatches: Vec<Swatch> = buckets.into_values()
+            .map(|(count, sum)| Swatch { color: sum.map(|s| (s / count) as u8), count })
+            .collect();
+        let Some(dominant) = swatches.iter().max_by_key(|swatch| swatch.count) else { return Palette::default() };
+
+        let score = |swatch: &Swatch, colorful: bool| {
+            let (saturation, lightness) = swatch.saturation_lightness();
+            // Too dark or too light and any color it has barely shows.
+            let usable = (0.2..=0.8).contains(&lightness) && (saturation >= 0.35) == colorful;
+            let weight = if colorful { saturation } else { 1.0 - saturation };
+            usable.then_some(swatch.count as f32 * weight)
+        };
+        let best = |colorful: bool| swatches.iter()
+            .filter_map(|swatch| Some((score(swatch, colorful)?, swatch)))
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .map_or(dominant.rgba(), |(_, swatch)| swatch.rgba());
+
+        Palette { dominant: dominant.rgba(), vibrant: best(true), muted: best(false), text: text_on(dominant.rgba()) }
+    }
+
+    /// The color `tint` asks for, `None` for `Tint::None`.
+    pub fn pick(&self, tint: Tint) -> Option<Rgba<u8>> {
+        match tint {
+            Tint::None => None,
+            Tint::Dominant => Some(self.dominant),
+            Tint::Vibrant => Some(self.vibrant),
+            Tint::Muted => Some(self.muted),
+        }
+    }
+}
+
+/// WCAG relative luminance, 0 for black to 1 for white. Alpha is ignored.
+pub fn luminance(color: Rgba<u8>) -> f32 {
+    let linear = |c: u8| {
+        let c = c as f32 / 255.0;
+        if c <= 0.03928 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    };
+    0.2126 * linear(color[0]) + 0.7152 * linear(color[1]) + 0.0722 * linear(color[2])
+}
+
+/// WCAG contrast ratio between two colors, from 1 (the same) to 21 (black on white).
+pub fn contrast(a: Rgba<u8>, b: Rgba<u8>) -> f32 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// Black or white, whichever has more contrast on `background`. Either way it's at least `MIN_CONTRAST`.
+pub fn text_on(background: Rgba<u8>) -> Rgba<u8> {
+    if contrast(WHITE, background) >= contrast(BLACK, background) { WHITE } else { BLACK }
+}
+
+/// `color` if it's readable on `background`, otherwise whichever of black and white is.
+pub fn readable(color: Rgba<u8>, background: Rgba<u8>) -> Rgba<u8> {
+    if contrast(color, background) >= MIN_CONTRAST { color } else { text_on(background) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contrast_ratios() {
+        assert!((contrast(BLACK, WHITE) - 21.0).abs() < 0.01);
+        assert_eq!(contrast(GREY, GREY), 1.0);
+        // Mid grey is on the dark side.
+        assert_eq!(text_on(GREY), BLACK);
+        assert_eq!(text_on(Rgba([40, 20, 90, 255])), WHITE);
+        for c in (0..=255).step_by(15) {
+            let background = Rgba([c, 255 - c, c / 2, 255]);
+            assert!(contrast(text_on(background), background) >= MIN_CONTRAST);
+        }
+        assert_eq!(readable(Rgba([255, 204, 0, 255]), BLACK), Rgba([255, 204, 0, 255]));
+        assert_eq!(readable(Rgba([255, 204, 0, 255]), WHITE), BLACK);
+    }
+
+    #[test]
+    fn picks_colors_out_of_art() {
+        // Mostly dull navy, a splash of orange, and a transparent border that shouldn't count.
+        let mut art = RgbaImage::from_pixel(100, 100, Rgba([0, 0, 0, 0]));
+        for (x, y, pixel) in art.enumerate_pixels_mut() {
+            if (10..90).contains(&x) && (10..90).contains(&y) {
+                *pixel = if x < 60 { Rgba([40, 48, 72, 255]) } else { Rgba([240, 120, 16, 255]) };
+            }
+        }
+        let palette = Palette::of(&DynamicImage::ImageRgba8(art));
+        let close = |a: Rgba<u8>, b: [u8; 3]| a.0.iter().zip(b).all(|(a, b)| a.abs_diff(b) <= 16);
+        assert!(close(palette.dominant, [40, 48, 72]), "{:?}", palette.dominant);
+        assert!(close(palette.vibrant, [240, 120, 16]), "{:?}", palette.vibrant);
+        assert!(close(palette.muted, [40, 48, 72]), "{:?}", palette.muted);
+        assert_eq!(palette.text, WHITE);
+
+        assert_eq!(Palette::of(&DynamicImage::new_rgba8(8, 8)), Palette::default());
+    }
+}
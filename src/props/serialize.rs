@@ -0,0 +1,371 @@
+//! Serde support for `SpectreProps`.
+//!
+//! The thumbnail is the awkward part, so how it gets written is picked when serializing with a `ThumbEncoding`.
+//! It ends up as one of these, or not at all:
+//! ```json
+//! "thumbnail": { "png": "iVBORw0KGgo..." }
+//! "thumbnail": { "path": "/tmp/spectre/3f2a....png" }
+//! "thumbnail": { "hash": "3f2a..." }
+//! ```
+//! Plain `Serialize` on `SpectreProps` uses `ThumbEncoding::Base64Png`.
+use super::*;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{de::Error as _, ser::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::{fs, io::{Cursor, Error, ErrorKind}, path::PathBuf, time::Duration};
+
+/// How to write the thumbnail when serializing a `SpectreProps`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ThumbEncoding {
+    /// A base64 encoded PNG, right there in the output.
+    #[default]
+    Base64Png,
+    /// Save the thumbnail as a PNG in this directory and write its path.
+    File(PathBuf),
+    /// Just the `thumb_hash` of the thumbnail, enough to tell if the art changed.
+    Hash,
+    /// Leave the thumbnail out entirely.
+    Omit,
+}
+
+impl FromStr for ThumbEncoding {
+    type Err = String;
+    /// Parses `png`, `hash`, `omit` or `file:<dir>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "png" => Ok(ThumbEncoding::Base64Png),
+            "hash" => Ok(ThumbEncoding::Hash),
+            "omit" => Ok(ThumbEncoding::Omit),
+            _ => match s.strip_prefix("file:") {
+                Some(dir) if !dir.is_empty() => Ok(ThumbEncoding::File(PathBuf::from(dir))),
+                _ => Err(format!("Unknown thumbnail encoding: {} (expected png, hash, omit or file:<dir>)", s)),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Thumb {
+    Png(String),
+    Path(PathBuf),
+    Hash(String),
+}
+
+/// What a `SourceApp` looks like on the wire. The icon gets the same `ThumbEncoding` as the thumbnail.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct SourceRepr {
+    id: String,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    icon: Option<Thumb>,
+}
+
+/// What `SpectreProps` looks like on the wire.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct PropsRepr {
+    title: String,
+    artist: String,
+    album: String,
+    album_artist: Option<String>,
+    genres: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumbnail: Option<Thumb>,
+    track_number: Option<i32>,
+    track_count: Option<i32>,
+    playback_type: SPT,
+    subtitle: Option<String>,
+    status: PlaybackStatus,
+    /// In seconds, like the duration.
+    position: Option<f64>,
+    duration: Option<f64>,
+    shuffle: Option<bool>,
+    repeat: Option<RepeatMode>,
+    source: SourceRepr,
+}
+impl Default for PropsRepr {
+    fn default() -> Self {
+        let props = SpectreProps::new();
+        PropsRepr {
+            title: props.title,
+            artist: props.artist,
+            album: props.album,
+            album_artist: None,
+            genres: vec![],
+            thumbnail: None,
+            track_number: None,
+            track_count: None,
+            playback_type: props.playback_type,
+            subtitle: None,
+            status: props.status,
+            position: None,
+            duration: None,
+            shuffle: None,
+            repeat: None,
+            source: SourceRepr::default(),
+        }
+    }
+}
+
+fn from_secs(secs: Option<f64>) -> Result<Option<Duration>, String> {
+    secs.map(|s| Duration::try_from_secs_f64(s).map_err(|_| format!("Bad time: {}", s))).transpose()
+}
+
+/// Hex SHA-256 of a thumbnail's size and RGBA pixels. Doesn't depend on how the image was encoded.
+pub fn thumb_hash(img: &DynamicImage) -> String {
+    let rgba = img.to_rgba8();
+    let mut hasher = Sha256::new();
+    hasher.update(rgba.width().to_le_bytes());
+    hasher.update(rgba.height().to_le_bytes());
+    hasher.update(rgba.as_raw());
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn to_png(img: &DynamicImage) -> Result<Vec<u8>, Error> {
+    let mut cursor = Cursor::new(Vec::new());
+    img.write_to(&mut cursor, image::ImageFormat::Png).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    Ok(cursor.into_inner())
+}
+
+impl Thumb {
+    fn encode(img: &DynamicImage, encoding: &ThumbEncoding) -> Result<Option<Self>, Error> {
+        Ok(match encoding {
+            ThumbEncoding::Base64Png => Some(Thumb::Png(STANDARD.encode(to_png(img)?))),
+            ThumbEncoding::File(dir) => {
+                // Named by hash so the same art only ever gets written once.
+                fs::create_dir_all(dir)?;
+                let path = dir.join(format!("{}.png", thumb_hash(img)));
+                if !path.exists() {
+                    fs::write(&path, to_png(img)?)?;
+                }
+                Some(Thumb::Path(path))
+            }
+            ThumbEncoding::Hash => Some(Thumb::Hash(thumb_hash(img))),
+            ThumbEncoding::Omit => None,
+        })
+    }
+
+    /// Gets the image back. A hash can't be turned back into art, so that gets the `ERROR_THUMB`.
+    fn decode(&self) -> Result<DynamicImage, Error> {
+        fn bad_data(e: impl std::error::Error + Send + Sync + 'static) -> Error {
+            Error::new(ErrorKind::InvalidData, e)
+        }
+        Ok(match self {
+            Thumb::Png(data) => image::load_from_memory(&STANDARD.decode(data).map_err(bad_data)?).map_err(bad_data)?,
+            Thumb::Path(path) => image::open(path).map_err(bad_data)?,
+            Thumb::Hash(_) => ERROR_THUMB.clone(),
+        })
+    }
+}
+
+/// A `SpectreProps` with the `ThumbEncoding` to serialize it with. Get one from `SpectreProps::with_thumbs`.
+pub struct SerProps<'a> {
+    props: &'a SpectreProps,
+    thumb: &'a ThumbEncoding,
+}
+
+impl Serialize for SerProps<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let props = self.props;
+        PropsRepr {
+            title: props.title.clone(),
+            artist: props.artist.clone(),
+            album: props.album.clone(),
+            album_artist: props.album_artist.clone(),
+            genres: props.genres.clone(),
+            thumbnail: Thumb::encode(&props.thumbnail, self.thumb).map_err(S::Error::custom)?,
+            track_number: props.track_number,
+            track_count: props.track_count,
+            playback_type: props.playback_type,
+            subtitle: props.subtitle.clone(),
+            status: props.status,
+            position: props.position.map(|p| p.as_secs_f64()),
+            duration: props.duration.map(|d| d.as_secs_f64()),
+            shuffle: props.shuffle,
+            repeat: props.repeat,
+            source: SourceRepr {
+                id: props.source.id.clone(),
+                name: props.source.name.clone(),
+                icon: match &props.source.icon {
+                    Some(icon) => Thumb::encode(icon, self.thumb).map_err(S::Error::custom)?,
+                    None => None,
+                },
+            },
+        }.serialize(serializer)
+    }
+}
+
+impl SpectreProps {
+    /// Pairs the props with a `ThumbEncoding`, for serializing them with something other than a base64 PNG.
+    pub fn with_thumbs<'a>(&'a self, thumb: &'a ThumbEncoding) -> SerProps<'a> {
+        SerProps { props: self, thumb }
+    }
+
+    pub fn to_json(&self, thumb: &ThumbEncoding) -> Result<String, Error> {
+        serde_json::to_string_pretty(&self.with_thumbs(thumb)).map_err(Error::other)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+impl Serialize for SpectreProps {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.with_thumbs(&ThumbEncoding::Base64Png).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SpectreProps {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = PropsRepr::deserialize(deserializer)?;
+        let thumbnail = match &repr.thumbnail {
+            Some(thumb) => thumb.decode().map_err(D::Error::custom)?,
+            None => ERROR_THUMB.clone(),
+        };
+        Ok(SpectreProps {
+            title: repr.title,
+            artist: repr.artist,
+            album: repr.album,
+            album_artist: repr.album_artist,
+            genres: repr.genres,
+            thumbnail,
+            track_number: repr.track_number,
+            track_count: repr.track_count,
+            playback_type: repr.playback_type,
+            subtitle: repr.subtitle,
+            status: repr.status,
+            position: from_secs(repr.position).map_err(D::Error::custom)?,
+            duration: from_secs(repr.duration).map_err(D::Error::custom)?,
+            shuffle: repr.shuffle,
+            repeat: repr.repeat,
+            source: SourceApp {
+                id: repr.source.id,
+                name: repr.source.name,
+                // Unlike the thumbnail there's nothing to stand in for a missing icon, so a hash is just no icon.
+                icon: match repr.source.icon {
+                    Some(Thumb::Hash(_)) | None => None,
+                    Some(icon) => Some(icon.decode().map_err(D::Error::custom)?),
+                },
+            },
+        })
+    }
+}
+
+impl Serialize for SPT {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+impl<'de> Deserialize<'de> for SPT {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for PlaybackStatus {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+impl<'de> Deserialize<'de> for PlaybackStatus {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for RepeatMode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+impl<'de> Deserialize<'de> for RepeatMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn props() -> SpectreProps {
+        let mut thumbnail = DynamicImage::new_rgba8(4, 4);
+        thumbnail.as_mut_rgba8().unwrap().put_pixel(1, 2, image::Rgba([10, 20, 30, 255]));
+        SpectreProps {
+            title: "Ghost Town".to_string(),
+            genres: vec!["Hip Hop".to_string()],
+            thumbnail,
+            track_number: Some(6),
+            playback_type: SPT::AUDIO,
+            status: PlaybackStatus::Playing,
+            position: Some(Duration::from_millis(83_500)),
+            duration: Some(Duration::from_secs(271)),
+            repeat: Some(RepeatMode::Track),
+            source: SourceApp { icon: Some(DynamicImage::new_rgba8(2, 2)), ..SourceApp::from_id("Spotify.exe") },
+            ..SpectreProps::new()
+        }
+    }
+
+    #[test]
+    fn base64_round_trip() {
+        let props = props();
+        let json = serde_json::to_string(&props).unwrap();
+        let back: SpectreProps = serde_json::from_str(&json).unwrap();
+        assert!(back.same_track(&props));
+        assert_eq!(back.genres, props.genres);
+        assert_eq!(back.playback_type, SPT::AUDIO);
+        assert_eq!(back.status, PlaybackStatus::Playing);
+        assert_eq!((back.position, back.duration), (props.position, props.duration));
+        assert_eq!((back.shuffle, back.repeat), (None, Some(RepeatMode::Track)));
+        assert!(back.source == props.source);
+        assert_eq!(back.thumbnail.to_rgba8(), props.thumbnail.to_rgba8());
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let props = props();
+        let json = props.to_json(&ThumbEncoding::File(dir.path().to_path_buf())).unwrap();
+        let value: Value = serde_json::from_str(&json).unwrap();
+        let path = PathBuf::from(value["thumbnail"]["path"].as_str().unwrap());
+        assert!(path.starts_with(dir.path()));
+        let back = SpectreProps::from_json(&json).unwrap();
+        assert_eq!(back.thumbnail.to_rgba8(), props.thumbnail.to_rgba8());
+    }
+
+    #[test]
+    fn hash_and_omit() {
+        let props = props();
+        let hashed: Value = serde_json::from_str(&props.to_json(&ThumbEncoding::Hash).unwrap()).unwrap();
+        assert_eq!(hashed["thumbnail"]["hash"].as_str().unwrap(), thumb_hash(&props.thumbnail));
+        assert_ne!(thumb_hash(&props.thumbnail), thumb_hash(&ERROR_THUMB));
+
+        let omitted = props.to_json(&ThumbEncoding::Omit).unwrap();
+        assert!(serde_json::from_str::<Value>(&omitted).unwrap().get("thumbnail").is_none());
+        // Neither can get the art back.
+        assert!(SpectreProps::from_json(&omitted).unwrap().thumbnail == *ERROR_THUMB);
+        assert!(SpectreProps::from_json(&hashed.to_string()).unwrap().thumbnail == *ERROR_THUMB);
+    }
+
+    #[test]
+    fn missing_fields_get_defaults() {
+        let props = SpectreProps::from_json(r#"{ "title": "Just A Title", "playback_type": "video" }"#).unwrap();
+        assert_eq!(props.artist, UNKNOWN_ARTIST);
+        assert_eq!(props.playback_type, SPT::VIDEO);
+        assert_eq!(props.status, PlaybackStatus::Unknown);
+        assert!(SpectreProps::from_json(r#"{ "playback_type": "smell" }"#).is_err());
+        assert!(SpectreProps::from_json(r#"{ "position": -1.0 }"#).is_err());
+    }
+
+    #[test]
+    fn parse_encoding() {
+        assert_eq!("png".parse(), Ok(ThumbEncoding::Base64Png));
+        assert_eq!("file:/tmp/art".parse(), Ok(ThumbEncoding::File(PathBuf::from("/tmp/art"))));
+        assert!("file:".parse::<ThumbEncoding>().is_err());
+        assert!("jpeg".parse::<ThumbEncoding>().is_err());
+    }
+}
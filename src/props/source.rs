@@ -0,0 +1,78 @@
+use super::*;
+
+/// The app a session belongs to.
+#[derive(Clone, Default, PartialEq)]
+pub struct SourceApp {
+    /// What the OS knows the app as: the AUMID on Windows, the desktop entry (or bus name) for MPRIS.
+    pub id: String,
+    /// Something fit to show a person, e.g. `Spotify`.
+    pub name: String,
+    pub icon: Option<DynamicImage>,
+}
+
+impl SourceApp {
+    /// A `SourceApp` with nothing but an id to go on, named after the id.
+    pub fn from_id(id: &str) -> Self {
+        SourceApp { id: id.to_string(), name: name_from_id(id), icon: None }
+    }
+
+    /// Whether `pattern` is in the id or the name, ignoring case.
+    pub fn matches(&self, pattern: &str) -> bool {
+        let pattern = pattern.to_lowercase();
+        self.id.to_lowercase().contains(&pattern) || self.name.to_lowercase().contains(&pattern)
+    }
+}
+
+/// Makes the best name it can out of an app id, for when the app doesn't have a proper one.
+///
+/// `SpotifyAB.SpotifyMusic_zpdnekdrzrea0!Spotify` and `Spotify.exe` both become `Spotify`,
+/// `org.videolan.vlc` becomes `Vlc`.
+pub fn name_from_id(id: &str) -> String {
+    // Packaged apps are Package!App, and it's the App bit that means something.
+    let id = id.rsplit('!').next().unwrap_or(id);
+    let id = id.rsplit(['\\', '/']).next().unwrap_or(id);
+    let id = match id.len().checked_sub(4) {
+        Some(cut) if id.is_char_boundary(cut) && id[cut..].eq_ignore_ascii_case(".exe") => &id[..cut],
+        _ => id,
+    };
+    let name = id.rsplit('.').find(|part| !part.is_empty()).unwrap_or(id);
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+impl fmt::Debug for SourceApp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SourceApp")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("icon", &self.icon.as_ref().map(|icon| format!("DynamicImage [{} x {}]", icon.width(), icon.height())))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_from_ids() {
+        assert_eq!(name_from_id("SpotifyAB.SpotifyMusic_zpdnekdrzrea0!Spotify"), "Spotify");
+        assert_eq!(name_from_id("Spotify.exe"), "Spotify");
+        assert_eq!(name_from_id(r"C:\Program Files\VideoLAN\VLC\vlc.EXE"), "Vlc");
+        assert_eq!(name_from_id("Microsoft.ZuneMusic_8wekyb3d8bbwe!Microsoft.ZuneMusic"), "ZuneMusic");
+        assert_eq!(name_from_id("org.videolan.vlc"), "Vlc");
+        assert_eq!(name_from_id("firefox"), "Firefox");
+        assert_eq!(name_from_id(""), "");
+    }
+
+    #[test]
+    fn matches_id_or_name() {
+        let app = SourceApp { id: "SpotifyAB.SpotifyMusic_zpdnekdrzrea0!Spotify".to_string(), name: "Spotify Music".to_string(), icon: None };
+        assert!(app.matches("spotifyab"));
+        assert!(app.matches("music"));
+        assert!(!app.matches("firefox"));
+    }
+}
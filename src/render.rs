@@ -0,0 +1,594 @@
+pub mod text;
+mod fonts;
+#[cfg(test)]
+mod snapshots;
+
+use crate::config::opt_hex_color;
+use crate::props::*;
+use image::{imageops, Rgba, RgbaImage};
+use serde::Deserialize;
+use text::*;
+
+/// What fills the toast behind the art and text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackgroundStyle {
+    /// Just the background color.
+    #[default]
+    Solid,
+    /// The album art, scaled up to cover the toast, blurred and darkened so the text stays readable.
+    Blurred,
+    /// One of the art's colors, the one the theme's `tint` picks or else the dominant one. See `Verdict::settings`.
+    Palette,
+}
+
+/// Which way text lines up in its box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// A box on the toast, in pixels from the top left corner of its card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Area {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+impl Area {
+    fn scaled(&self, scale: f32) -> Area {
+        let px = |size: u32| (size as f32 * scale).round() as u32;
+        Area { x: px(self.x), y: px(self.y), width: px(self.width), height: px(self.height) }
+    }
+}
+
+/// Where a line of text goes, with its top left corner at (`x`, `y`), and anything it draws differently from the
+/// theme's other lines.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TextBox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    #[serde(default)]
+    pub align: Align,
+    pub size: Option<f32>,
+    #[serde(default, deserialize_with = "opt_hex_color")]
+    pub color: Option<Rgba<u8>>,
+    pub font: Option<FontChoice>,
+}
+impl TextBox {
+    fn scaled(&self, scale: f32) -> TextBox {
+        let px = |size: u32| (size as f32 * scale).round() as u32;
+        TextBox { x: px(self.x), y: px(self.y), width: px(self.width), size: self.size.map(|size| size * scale), ..self.clone() }
+    }
+}
+
+/// Where each part of a toast goes, for themes that place things themselves. Anything left out isn't drawn.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Elements {
+    /// Fitted into the box, keeping its aspect ratio.
+    pub art: Option<Area>,
+    /// The name of the app that's playing. Unlike the usual layout, no icon.
+    pub source: Option<TextBox>,
+    pub title: Option<TextBox>,
+    pub artist: Option<TextBox>,
+    pub album: Option<TextBox>,
+    /// "Track 6/7".
+    pub track: Option<TextBox>,
+    pub progress: Option<Area>,
+}
+impl Elements {
+    fn scaled(&self, scale: f32) -> Elements {
+        let text = |text: &Option<TextBox>| text.as_ref().map(|text| text.scaled(scale));
+        Elements {
+            art: self.art.map(|art| art.scaled(scale)),
+            source: text(&self.source),
+            title: text(&self.title),
+            artist: text(&self.artist),
+            album: text(&self.album),
+            track: text(&self.track),
+            progress: self.progress.map(|progress| progress.scaled(scale)),
+        }
+    }
+}
+
+/// Where things go on a toast and what they look like. Sizes are in pixels.
+///
+/// The toast is a card `shadow` pixels in from every edge, with the shadow around it. Unless there are `elements`
+/// to say where things go, the art sits on the left of the card with the text next to it, and the progress bar
+/// runs along the bottom.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    /// The whole toast, shadow included.
+    pub width: u32,
+    pub height: u32,
+    /// Radius of the card's corners. 0 for square ones.
+    pub radius: u32,
+    /// How far the card's drop shadow spreads. 0 for none.
+    pub shadow: u32,
+    /// The card's shadow, and the art's.
+    pub shadow_color: Rgba<u8>,
+    /// Space between the edge of the toast and everything on it, and between the art and the text.
+    pub padding: u32,
+    /// Album art is drawn as a square this big, vertically centered on the left.
+    pub art_size: u32,
+    pub art_shape: ArtShape,
+    /// Radius of the art's corners, when it's `Square`.
+    pub art_radius: u32,
+    /// How far the art's drop shadow spreads, taken from the art's space. 0 for none.
+    pub art_shadow: u32,
+    pub background: Rgba<u8>,
+    pub background_style: BackgroundStyle,
+    /// How blurry a `Blurred` background is, as the Gaussian's sigma.
+    pub blur: f32,
+    /// How much a `Blurred` background is darkened, from 0 for not at all to 1 for black.
+    pub dim: f32,
+    pub title_color: Rgba<u8>,
+    pub text_color: Rgba<u8>,
+    pub title_size: f32,
+    pub text_size: f32,
+    pub title_font: FontChoice,
+    pub text_font: FontChoice,
+    /// What a title too long for the toast does. Other lines get an ellipsis, unless this is `Clip`.
+    pub overflow: Overflow,
+    /// Extra space between lines of text.
+    pub line_spacing: u32,
+    /// Height of the progress bar along the bottom edge. 0 turns it off.
+    pub progress_height: u32,
+    /// The played part of the progress bar. The rest of it is this color faded into the background.
+    pub progress_color: Rgba<u8>,
+    /// Put the name and icon of the app that's playing above the title.
+    pub show_source: bool,
+    pub source_size: f32,
+    /// Where everything goes, instead of the usual layout. `padding`, `art_size` and `show_source` don't count then.
+    pub elements: Option<Elements>,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            width: 480,
+            height: 160,
+            radius: 0,
+            shadow: 0,
+            shadow_color: Rgba([0, 0, 0, 160]),
+            padding: 16,
+            art_size: 128,
+            art_shape: ArtShape::Square,
+            art_radius: 0,
+            art_shadow: 0,
+            background: Rgba([126, 126, 126, 255]),
+            background_style: BackgroundStyle::Solid,
+            blur: 12.0,
+            dim: 0.45,
+            title_color: Rgba([255, 255, 255, 255]),
+            text_color: Rgba([230, 230, 230, 255]),
+            title_size: 24.0,
+            text_size: 16.0,
+            title_font: FontChoice::Bold,
+            text_font: FontChoice::Regular,
+            overflow: Overflow::Ellipsis,
+            line_spacing: 2,
+            progress_height: 4,
+            progress_color: Rgba([255, 255, 255, 255]),
+            show_source: true,
+            source_size: 13.0,
+            elements: None,
+        }
+    }
+}
+
+impl Layout {
+    /// The same layout with every size multiplied by `scale`, for high DPI monitors.
+    pub fn scaled(&self, scale: f32) -> Layout {
+        let px = |size: u32| (size as f32 * scale).round() as u32;
+        Layout {
+            width: px(self.width),
+            height: px(self.height),
+            radius: px(self.radius),
+            shadow: px(self.shadow),
+            padding: px(self.padding),
+            art_size: px(self.art_size),
+            art_radius: px(self.art_radius),
+            art_shadow: px(self.art_shadow),
+            blur: self.blur * scale,
+            title_size: self.title_size * scale,
+            text_size: self.text_size * scale,
+            line_spacing: px(self.line_spacing),
+            progress_height: px(self.progress_height),
+            source_size: self.source_size * scale,
+            elements: self.elements.as_ref().map(|elements| elements.scaled(scale)),
+            ..self.clone()
+        }
+    }
+
+    /// The same layout on `palette`'s `tint` color, keeping the background's alpha. Colors that don't read on it
+    /// anymore become black or white.
+    pub fn tinted(&self, palette: &Palette, tint: Tint) -> Layout {
+        let Some(Rgba([r, g, b, _])) = palette.pick(tint) else { return self.clone() };
+        let background = Rgba([r, g, b, self.background[3]]);
+        Layout {
+            background,
+            title_color: readable(self.title_color, background),
+            text_color: readable(self.text_color, background),
+            progress_color: readable(self.progress_color, background),
+            elements: self.elements.as_ref().map(|elements| Elements {
+                source: elements.source.as_ref().map(|text| readable_on(text, background)),
+                title: elements.title.as_ref().map(|text| readable_on(text, background)),
+                artist: elements.artist.as_ref().map(|text| readable_on(text, background)),
+                album: elements.album.as_ref().map(|text| readable_on(text, background)),
+                track: elements.track.as_ref().map(|text| readable_on(text, background)),
+                ..elements.clone()
+            }),
+            ..self.clone()
+        }
+    }
+
+    /// The card in the middle of the shadow, as a layout of its own.
+    fn card(&self) -> Layout {
+        Layout {
+            width: self.width.saturating_sub(self.shadow * 2).max(1),
+            height: self.height.saturating_sub(self.shadow * 2).max(1),
+            shadow: 0,
+            ..self.clone()
+        }
+    }
+
+    fn title_style(&self) -> TextStyle {
+        TextStyle { font: self.title_font.font(), size: self.title_size, color: self.title_color }
+    }
+
+    fn text_style(&self) -> TextStyle {
+        TextStyle { font: self.text_font.font(), size: self.text_size, color: self.text_color }
+    }
+}
+
+/// `text` with its own color, if it has one, swapped for black or white when it doesn't read on `background`.
+fn readable_on(text: &TextBox, background: Rgba<u8>) -> TextBox {
+    TextBox { color: text.color.map(|color| readable(color, background)), ..text.clone() }
+}
+
+/// A line of text on a toast, with the style it's drawn in and maybe an icon in front of it.
+struct Line<'a> {
+    text: String,
+    style: TextStyle,
+    overflow: Overflow,
+    icon: Option<&'a DynamicImage>,
+}
+impl<'a> Line<'a> {
+    fn new(text: String, style: TextStyle, overflow: Overflow) -> Self {
+        Line { text, style, overflow, icon: None }
+    }
+}
+
+/// How lines other than the title deal with being too long: they never scroll.
+fn body_overflow(layout: &Layout) -> Overflow {
+    match layout.overflow {
+        Overflow::Clip => Overflow::Clip,
+        Overflow::Ellipsis | Overflow::Marquee => Overflow::Ellipsis,
+    }
+}
+
+/// Whether there's an album worth showing.
+fn has_album(props: &SpectreProps) -> bool {
+    !props.album.is_empty() && props.album != UNKNOWN_ALBUM
+}
+
+/// "Track 6/7", or just "Track 6" without a count. `None` without a track number.
+fn track_text(props: &SpectreProps) -> Option<String> {
+    match (props.track_number, props.track_count) {
+        (Some(n), Some(count)) => Some(format!("Track {}/{}", n, count)),
+        (Some(n), None) => Some(format!("Track {}", n)),
+        _ => None,
+    }
+}
+
+/// The lines of text a toast shows for `props`. Missing info gets no line at all.
+fn text_lines<'a>(props: &'a SpectreProps, layout: &Layout) -> Vec<Line<'a>> {
+    let (title, body) = (layout.title_style(), layout.text_style());
+    let overflow = body_overflow(layout);
+    let mut lines = vec![];
+    if layout.show_source && !props.source.name.is_empty() {
+        let caption = TextStyle { size: layout.source_size, ..body };
+        lines.push(Line { icon: props.source.icon.as_ref(), ..Line::new(props.source.name.clone(), caption, overflow) });
+    }
+    lines.push(Line::new(props.title.clone(), title, layout.overflow));
+    lines.push(Line::new(props.artist.clone(), body, overflow));
+    if has_album(props) {
+        lines.push(Line::new(props.album.clone(), body, overflow));
+    }
+    if let Some(track) = track_text(props) {
+        lines.push(Line::new(track, body, overflow));
+    }
+    lines
+}
+
+/// How much of the track has been played, from 0 to 1. `None` if the source didn't say how long the track is.
+fn progress(props: &SpectreProps) -> Option<f32> {
+    let (position, duration) = (props.position?, props.duration?);
+    if duration.is_zero() {
+        return None;
+    }
+    Some((position.as_secs_f32() / duration.as_secs_f32()).clamp(0.0, 1.0))
+}
+
+#[cfg_attr(not(any(windows, target_os = "linux")), allow(dead_code))]
+/// An RGBA pixel at `opacity`, with the color premultiplied by its alpha the way compositors want it.
+pub fn premultiply([r, g, b, a]: [u8; 4], opacity: u8) -> [u8; 4] {
+    let a = (a as u32 * opacity as u32 / 255) as u8;
+    let [r, g, b] = [r, g, b].map(|c| (c as u32 * a as u32 / 255) as u8);
+    [r, g, b, a]
+}
+
+/// Draws the progress bar filling `area`.
+fn draw_progress(canvas: &mut RgbaImage, progress: f32, area: Area, color: Rgba<u8>) {
+    let played = area.x + (area.width as f32 * progress).round() as u32;
+    for y in area.y..(area.y + area.height).min(canvas.height()) {
+        for x in area.x..(area.x + area.width).min(canvas.width()) {
+            let coverage = if x < played { 1.0 } else { 0.3 };
+            blend(canvas.get_pixel_mut(x, y), color, coverage);
+        }
+    }
+}
+
+/// Fills the toast with its art, blurred and dimmed, over the background color. The toast keeps the background's alpha.
+fn draw_blurred_background(canvas: &mut RgbaImage, art: &DynamicImage, layout: &Layout) {
+    // Blurring a quarter size copy looks the same once it's scaled back up, in a fraction of the time.
+    let small = art.resize_to_fill((layout.width / 4).max(1), (layout.height / 4).max(1), FilterType::Triangle).to_rgba8();
+    let small = if layout.blur > 0.0 { imageops::blur(&small, layout.blur / 4.0) } else { small };
+    let blurred = imageops::resize(&small, layout.width, layout.height, FilterType::Triangle);
+    let keep = 1.0 - layout.dim.clamp(0.0, 1.0);
+    for (pixel, blurred) in canvas.pixels_mut().zip(blurred.pixels()) {
+        let [r, g, b, a] = blurred.0;
+        let dim = |c: u8| (c as f32 * keep).round() as u8;
+        let alpha = pixel.0[3];
+        blend(pixel, Rgba([dim(r), dim(g), dim(b), a]), 1.0);
+        pixel.0[3] = alpha;
+    }
+}
+
+/// `art` fitted into a `width` by `height` box, cut to the layout's shape and with its shadow, if it has one, inside the box.
+fn shaped_art(art: &DynamicImage, width: u32, height: u32, layout: &Layout) -> RgbaImage {
+    let shadow = layout.art_shadow.min(width / 4).min(height / 4);
+    let (width, height) = ((width - shadow * 2).max(1), (height - shadow * 2).max(1));
+    let art = art.resize_centered(width, height, FilterType::Triangle).shaped(layout.art_shape, layout.art_radius);
+    if shadow == 0 {
+        return art.into_rgba8();
+    }
+    shadowed(&art.into_rgba8(), shadow, layout.shadow_color)
+}
+
+/// Where the text goes across the toast: its left edge and how wide it can be.
+fn text_area(layout: &Layout) -> (u32, u32) {
+    let x = layout.padding * 2 + layout.art_size;
+    (x, layout.width.saturating_sub(x + layout.padding))
+}
+
+/// How `text` looks in its box, given how lines look otherwise.
+fn text_box_style(text: &TextBox, style: TextStyle) -> TextStyle {
+    TextStyle {
+        font: text.font.as_ref().map_or(style.font, FontChoice::font),
+        size: text.size.unwrap_or(style.size),
+        color: text.color.unwrap_or(style.color),
+    }
+}
+
+/// Whether the toast for `props` has a marquee, and so needs a `Marquee` to scroll it.
+fn scrolls(props: &SpectreProps, layout: &Layout) -> bool {
+    let card = layout.card();
+    let (style, width) = match &card.elements {
+        None => (card.title_style(), text_area(&card).1),
+        Some(Elements { title: Some(title), .. }) => (text_box_style(title, card.title_style()), title.width),
+        Some(_) => return false,
+    };
+    layout.overflow == Overflow::Marquee && measure(&props.title, &style) > width as f32
+}
+
+/// Renders a whole toast for `props` into an RGBA image the size of the layout.
+///
+/// This doesn't touch any windowing system, so whatever ends up showing the toast only has to put the pixels on screen.
+pub fn render_toast(props: &SpectreProps, layout: &Layout) -> RgbaImage {
+    render_toast_at(props, layout, 0.0)
+}
+
+/// `render_toast` as it looks `t` seconds after it showed up, which only matters when it `scrolls`.
+pub fn render_toast_at(props: &SpectreProps, layout: &Layout, t: f32) -> RgbaImage {
+    let mut card = render_card(props, &layout.card(), &mut |canvas, text, x, y, width, style| {
+        draw_marquee(canvas, text, x, y, width, style, t);
+    });
+    if layout.radius > 0 {
+        round_corners(&mut card, layout.radius);
+    }
+    if layout.shadow > 0 {
+        return shadowed(&card, layout.shadow, layout.shadow_color);
+    }
+    card
+}
+
+/// Draws a title that scrolls: the text, the left end and top of its box, how wide the box is and the text's style.
+type DrawMarquee<'a> = &'a mut dyn FnMut(&mut RgbaImage, &str, i32, i32, u32, &TextStyle);
+
+/// Everything on the card, with `marquee` left to draw the title if it scrolls. The corners aren't rounded yet.
+fn render_card(props: &SpectreProps, card: &Layout, marquee: DrawMarquee) -> RgbaImage {
+    let mut canvas = RgbaImage::from_pixel(card.width, card.height, card.background);
+    if card.background_style == BackgroundStyle::Blurred {
+        draw_blurred_background(&mut canvas, &props.thumbnail, card);
+    }
+    match &card.elements {
+        Some(elements) => draw_elements(&mut canvas, props, card, elements, marquee),
+        None => draw_flow(&mut canvas, props, card, marquee),
+    }
+    canvas
+}
+
+/// A toast whose title scrolls, for showing frame after frame of it.
+///
+/// Everything but the title is only rendered once, so each frame only redraws the strip of the card the title is in,
+/// then rounds that strip's corners and puts it over the shadow like `render_toast_at` does for the whole card.
+#[cfg_attr(not(any(windows, target_os = "linux")), allow(dead_code))]
+pub struct Marquee {
+    /// The toast as of the last `at`.
+    frame: RgbaImage,
+    /// The card with everything but the title, before its corners are rounded.
+    card: RgbaImage,
+    /// A copy of `card` the title gets drawn on, put back the way `card` has it under the strip before every frame.
+    canvas: RgbaImage,
+    /// The card's shadow, which the card goes in the middle of, if the layout has one.
+    shadow: Option<RgbaImage>,
+    spread: u32,
+    radius: u32,
+    title: String,
+    style: TextStyle,
+    /// The left end and top of the title's box on the card, and how wide the box is.
+    title_at: (i32, i32, u32),
+    /// The part of the card the title can draw on.
+    strip: Area,
+}
+#[cfg_attr(not(any(windows, target_os = "linux")), allow(dead_code))]
+impl Marquee {
+    /// The toast for `props`, if its title `scrolls`.
+    pub fn new(props: &SpectreProps, layout: &Layout) -> Option<Self> {
+        if !scrolls(props, layout) {
+            return None;
+        }
+        let mut title = None;
+        let card = render_card(props, &layout.card(), &mut |_, text, x, y, width, style| {
+            title = Some((text.to_string(), (x, y, width), *style));
+        });
+        let (title, title_at, style) = title?;
+        let mut frame = card.clone();
+        if layout.radius > 0 {
+            round_corners(&mut frame, layout.radius);
+        }
+        let shadow = (layout.shadow > 0).then(|| shadow_of(&frame, layout.shadow, layout.shadow_color));
+        if let Some(shadow) = &shadow {
+            let mut under = shadow.clone();
+            imageops::overlay(&mut under, &frame, layout.shadow as i64, layout.shadow as i64);
+            frame = under;
+        }
+        // Glyphs can poke out of their line a bit, above and below.
+        let (x, y, width) = title_at;
+        let reach = (style.size / 2.0).ceil() as i32;
+        let (left, right) = (x.max(0), (x + width as i32).min(card.width() as i32));
+        let (top, bottom) = ((y - reach).max(0), (y + style.line_height().ceil() as i32 + reach).min(card.height() as i32));
+        let strip = Area {
+            x: left as u32,
+            y: top as u32,
+            width: (right - left).max(0) as u32,
+            height: (bottom - top).max(0) as u32,
+        };
+        let canvas = card.clone();
+        Some(Self { frame, card, canvas, shadow, spread: layout.shadow, radius: layout.radius, title, style, title_at, strip })
+    }
+
+    /// The toast as it looks `t` seconds after it showed up, the same as `render_toast_at` has it.
+    pub fn at(&mut self, t: f32) -> RgbaImage {
+        let Area { x, y, width, height } = self.strip;
+        imageops::replace(&mut self.canvas, &*imageops::crop_imm(&self.card, x, y, width, height), x as i64, y as i64);
+        let (title_x, title_y, title_width) = self.title_at;
+        draw_marquee(&mut self.canvas, &self.title, title_x, title_y, title_width, &self.style, t);
+        let mut strip = imageops::crop_imm(&self.canvas, x, y, width, height).to_image();
+        if self.radius > 0 {
+            round_corners_of_part(&mut strip, (x, y), self.card.dimensions(), self.radius);
+        }
+        if let Some(shadow) = &self.shadow {
+            let mut under = imageops::crop_imm(shadow, x + self.spread, y + self.spread, width, height).to_image();
+            imageops::overlay(&mut under, &strip, 0, 0);
+            strip = under;
+        }
+        imageops::replace(&mut self.frame, &strip, (x + self.spread) as i64, (y + self.spread) as i64);
+        self.frame.clone()
+    }
+}
+
+/// The usual layout: art on the left, the lines of text next to it, and the progress bar along the bottom.
+fn draw_flow(canvas: &mut RgbaImage, props: &SpectreProps, layout: &Layout, marquee: DrawMarquee) {
+    let art = shaped_art(&props.thumbnail, layout.art_size, layout.art_size, layout);
+    let art_y = layout.height.saturating_sub(layout.art_size) / 2;
+    imageops::overlay(canvas, &art, layout.padding as i64, art_y as i64);
+
+    let (text_x, text_width) = text_area(layout);
+    let lines = text_lines(props, layout);
+    let block_height: f32 = lines.iter().map(|line| line.style.line_height() + layout.line_spacing as f32).sum::<f32>()
+        - layout.line_spacing as f32;
+    let mut y = (layout.height as f32 - block_height) / 2.0;
+    for line in &lines {
+        let (mut x, mut width) = (text_x, text_width);
+        if let Some(icon) = line.icon {
+            // Square, as tall as the line, with a bit of a gap before the text.
+            let size = line.style.line_height().round() as u32;
+            let icon = icon.resize_centered(size, size, FilterType::Triangle).to_rgba8();
+            imageops::overlay(canvas, &icon, x as i64, y.round() as i64);
+            x += size + size / 3;
+            width = width.saturating_sub(size + size / 3);
+        }
+        let (x, top) = (x as i32, y.round() as i32);
+        match line.overflow {
+            Overflow::Marquee => marquee(canvas, &line.text, x, top, width, &line.style),
+            overflow => {
+                draw_text(canvas, &line.text, x, top, width, &line.style, overflow);
+            }
+        }
+        y += line.style.line_height() + layout.line_spacing as f32;
+    }
+
+    if let Some(progress) = progress(props).filter(|_| layout.progress_height > 0) {
+        let top = layout.height.saturating_sub(layout.progress_height);
+        let area = Area { x: 0, y: top, width: layout.width, height: layout.progress_height };
+        draw_progress(canvas, progress, area, layout.progress_color);
+    }
+}
+
+/// A theme's own layout: everything where its `elements` put it.
+fn draw_elements(canvas: &mut RgbaImage, props: &SpectreProps, layout: &Layout, elements: &Elements, marquee: DrawMarquee) {
+    if let Some(area) = elements.art {
+        let art = shaped_art(&props.thumbnail, area.width.max(1), area.height.max(1), layout);
+        imageops::overlay(canvas, &art, area.x as i64, area.y as i64);
+    }
+
+    let (title, body) = (layout.title_style(), layout.text_style());
+    let overflow = body_overflow(layout);
+    let source = (!props.source.name.is_empty()).then(|| props.source.name.clone());
+    let album = has_album(props).then(|| props.album.clone());
+    let texts = [
+        (&elements.source, source, TextStyle { size: layout.source_size, ..body }, overflow),
+        (&elements.title, Some(props.title.clone()), title, layout.overflow),
+        (&elements.artist, Some(props.artist.clone()), body, overflow),
+        (&elements.album, album, body, overflow),
+        (&elements.track, track_text(props), body, overflow),
+    ];
+    for (text_box, text, style, overflow) in texts {
+        let (Some(text_box), Some(text)) = (text_box, text) else { continue };
+        let style = text_box_style(text_box, style);
+        let shown = match overflow {
+            Overflow::Ellipsis => ellipsize(&text, &style, text_box.width),
+            Overflow::Clip | Overflow::Marquee => text.clone(),
+        };
+        // Text that doesn't fit takes up the whole box whichever way it's aligned.
+        let spare = text_box.width.saturating_sub(measure(&shown, &style).ceil() as u32);
+        let indent = match text_box.align {
+            Align::Left => 0,
+            Align::Center => spare / 2,
+            Align::Right => spare,
+        };
+        let (x, y, width) = ((text_box.x + indent) as i32, text_box.y as i32, text_box.width - indent);
+        match overflow {
+            Overflow::Marquee => marquee(canvas, &text, x, y, width, &style),
+            overflow => {
+                draw_text(canvas, &shown, x, y, width, &style, overflow);
+            }
+        }
+    }
+
+    if let (Some(area), Some(progress)) = (elements.progress, progress(props)) {
+        draw_progress(canvas, progress, area, layout.progress_color);
+    }
+}
@@ -0,0 +1,116 @@
+//! The system's fonts, for characters the bundled ones don't have, like CJK and emoji.
+//!
+//! Found through fontconfig (`fc-match`) on Linux, and among the fonts every install has on Windows.
+//! Each character only gets looked up once, and each font file only gets loaded once.
+use ab_glyph::{Font, FontArc, FontVec};
+use ab_glyph::FontRef;
+use std::{collections::HashMap, fs, path::{Path, PathBuf}, sync::{LazyLock, Mutex}};
+
+#[derive(Default)]
+struct Fallbacks {
+    /// Index into `fonts` of the font each character was found in, if it was.
+    chars: HashMap<char, Option<usize>>,
+    /// Every font file tried so far, with its index into `fonts` if it loaded.
+    files: HashMap<(PathBuf, u32), Option<usize>>,
+    fonts: Vec<FontArc>,
+}
+
+static FALLBACKS: LazyLock<Mutex<Fallbacks>> = LazyLock::new(Mutex::default);
+
+/// A system font that has `c`, if there's one.
+pub fn fallback_for(c: char) -> Option<FontArc> {
+    let mut fallbacks = FALLBACKS.lock().unwrap();
+    let found = match fallbacks.chars.get(&c) {
+        Some(found) => *found,
+        None => {
+            let found = candidates(c).into_iter().find_map(|file| {
+                let index = match fallbacks.files.get(&file) {
+                    Some(index) => *index,
+                    None => {
+                        let index = load(&file).map(|font| {
+                            fallbacks.fonts.push(font);
+                            fallbacks.fonts.len() - 1
+                        });
+                        fallbacks.files.insert(file, index);
+                        index
+                    }
+                }?;
+                (fallbacks.fonts[index].glyph_id(c).0 != 0).then_some(index)
+            });
+            fallbacks.chars.insert(c, found);
+            found
+        }
+    };
+    found.map(|index| fallbacks.fonts[index].clone())
+}
+
+/// Fonts themes asked for by file. They're kept for as long as the program runs, so each file is only read once.
+static FILES: LazyLock<Mutex<HashMap<PathBuf, Option<&'static FontRef<'static>>>>> = LazyLock::new(Mutex::default);
+
+/// The font in the file at `path`, if it's there and is one.
+pub fn from_file(path: &Path) -> Option<&'static FontRef<'static>> {
+    *FILES.lock().unwrap().entry(path.to_path_buf()).or_insert_with(|| {
+        let data: &'static [u8] = fs::read(path).ok()?.leak();
+        FontRef::try_from_slice(data).ok().map(|font| &*Box::leak(Box::new(font)))
+    })
+}
+
+fn load((path, index): &(PathBuf, u32)) -> Option<FontArc> {
+    FontVec::try_from_vec_and_index(fs::read(path).ok()?, *index).ok().map(FontArc::from)
+}
+
+/// Font files, and which font in them, that might have `c`. Best first.
+fn candidates(c: char) -> Vec<(PathBuf, u32)> {
+    // Goldens can't depend on what fonts the machine running the tests has, so tests get a font of their own,
+    // with just the characters they use.
+    if cfg!(test) {
+        return vec![(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/cjk.ttf"), 0)];
+    }
+    system_fonts(c)
+}
+
+#[cfg(target_os = "linux")]
+fn system_fonts(c: char) -> Vec<(PathBuf, u32)> {
+    let Ok(output) = std::process::Command::new("fc-match")
+        .args(["--format=%{file}\n%{index}", &format!(":charset={:x}", c as u32)])
+        .output() else { return vec![] };
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut lines = output.lines();
+    match (lines.next(), lines.next().and_then(|index| index.parse().ok())) {
+        (Some(file), Some(index)) if !file.is_empty() => vec![(PathBuf::from(file), index)],
+        _ => vec![],
+    }
+}
+/// Windows doesn't say which font has what, so it's the usual suspects: emoji, symbols, then the CJK fonts.
+#[cfg(windows)]
+fn system_fonts(_: char) -> Vec<(PathBuf, u32)> {
+    let dir = PathBuf::from(std::env::var_os("WINDIR").unwrap_or("C:\\Windows".into())).join("Fonts");
+    ["seguiemj.ttf", "seguisym.ttf", "YuGothM.ttc", "msyh.ttc", "malgun.ttf", "Nirmala.ttf", "ebrima.ttf", "arial.ttf"]
+        .iter().map(|file| (dir.join(file), 0)).collect()
+}
+#[cfg(not(any(windows, target_os = "linux")))]
+fn system_fonts(_: char) -> Vec<(PathBuf, u32)> {
+    vec![]
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_system_fonts() {
+        let Some(file) = system_fonts('A').into_iter().next() else {
+            return eprintln!("No fontconfig, skipping");
+        };
+        let font = load(&file).unwrap();
+        assert_ne!(font.glyph_id('A').0, 0);
+    }
+
+    #[test]
+    fn loads_font_files() {
+        let bundled = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/render/DejaVuSans-Bold.ttf");
+        let font = from_file(&bundled).unwrap();
+        assert!(std::ptr::eq(font, from_file(&bundled).unwrap()));
+        assert!(from_file(Path::new("no/such/font.ttf")).is_none());
+    }
+}
@@ -0,0 +1,222 @@
+//! Golden image tests for `render_toast`.
+//!
+//! Each case renders a toast and compares it against `tests/golden/<name>.png`. When they don't match, the render
+//! and a diff (differing pixels in red) get written to `target/golden-diff/` to look at.
+//! Run with `UPDATE_GOLDEN=1` to (re)write the goldens after an intended visual change.
+use super::*;
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::{env, fs, path::{Path, PathBuf}};
+
+/// How far apart a channel can be before the pixel counts as different. Glyph antialiasing can wobble a little.
+const CHANNEL_TOLERANCE: u8 = 8;
+/// Fraction of pixels that may differ before the snapshot fails.
+const PIXEL_TOLERANCE: f64 = 0.002;
+
+fn manifest_dir() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn art(name: &str) -> DynamicImage {
+    fit_thumb(image::open(manifest_dir().join("tests/fixtures/art").join(name)).unwrap())
+}
+
+fn props(title: &str, artist: &str, album: &str) -> SpectreProps {
+    SpectreProps {
+        title: title.to_string(),
+        artist: artist.to_string(),
+        album: album.to_string(),
+        thumbnail: art("square.png"),
+        track_number: Some(6),
+        track_count: Some(7),
+        ..SpectreProps::new()
+    }
+}
+
+/// Paints the pixels that differ between the two images red, over a faded copy of `expected`.
+fn diff_image(expected: &RgbaImage, actual: &RgbaImage) -> (RgbaImage, usize) {
+    let mut diff = RgbaImage::new(expected.width(), expected.height());
+    let mut bad = 0;
+    for (x, y, want) in expected.enumerate_pixels() {
+        let got = actual.get_pixel(x, y);
+        let differs = want.0.iter().zip(got.0.iter()).any(|(a, b)| a.abs_diff(*b) > CHANNEL_TOLERANCE);
+        let px = if differs {
+            bad += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let [r, g, b, _] = want.0;
+            Rgba([r / 3, g / 3, b / 3, 255])
+        };
+        diff.put_pixel(x, y, px);
+    }
+    (diff, bad)
+}
+
+fn assert_snapshot(name: &str, actual: &RgbaImage) {
+    let golden = manifest_dir().join("tests/golden").join(format!("{}.png", name));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(golden.parent().unwrap()).unwrap();
+        actual.save(&golden).unwrap();
+        return;
+    }
+    let out_dir: PathBuf = manifest_dir().join("target/golden-diff");
+    let fail = |why: String| {
+        fs::create_dir_all(&out_dir).unwrap();
+        let actual_path = out_dir.join(format!("{}.actual.png", name));
+        actual.save(&actual_path).unwrap();
+        panic!("Snapshot {} failed: {}\nRender saved to {}\nRun with UPDATE_GOLDEN=1 if the change is intended.",
+            name, why, actual_path.display());
+    };
+    let expected = match image::open(&golden) {
+        Ok(img) => img.to_rgba8(),
+        Err(e) => return fail(format!("couldn't open {}: {}", golden.display(), e)),
+    };
+    if expected.dimensions() != actual.dimensions() {
+        return fail(format!("size is {:?}, expected {:?}", actual.dimensions(), expected.dimensions()));
+    }
+    let (diff, bad) = diff_image(&expected, actual);
+    let allowed = (expected.width() * expected.height()) as f64 * PIXEL_TOLERANCE;
+    if bad as f64 > allowed {
+        fs::create_dir_all(&out_dir).unwrap();
+        diff.save(out_dir.join(format!("{}.diff.png", name))).unwrap();
+        fail(format!("{} pixels differ, {} allowed. Diff saved next to the render", bad, allowed as usize));
+    }
+}
+
+#[test]
+fn snapshot_full() {
+    let toast = render_toast(&props("Ghost Town", "Kanye West, PARTYNEXTDOOR", "ye"), &Layout::default());
+    assert_snapshot("full", &toast);
+}
+
+#[test]
+fn snapshot_long_title() {
+    let props = props(
+        "Pneumonoultramicroscopicsilicovolcanoconiosis (Extended Club Remix) [feat. Everybody]",
+        "An Artist With A Name So Long It Cannot Possibly Fit",
+        "The Album Title That Also Goes On And On And On",
+    );
+    assert_snapshot("long_title", &render_toast(&props, &Layout::default()));
+}
+
+#[test]
+fn snapshot_missing_art() {
+    let props = SpectreProps {
+        thumbnail: ERROR_THUMB.clone(),
+        ..props("Unreleased Demo", "Nobody", "Bootlegs")
+    };
+    assert_snapshot("missing_art", &render_toast(&props, &Layout::default()));
+}
+
+#[test]
+fn snapshot_cjk() {
+    let props = props("夜に駆ける", "YOASOBI", "THE BOOK");
+    assert_snapshot("cjk", &render_toast(&props, &Layout::default()));
+}
+
+#[test]
+fn snapshot_no_album() {
+    let props = SpectreProps {
+        track_number: None,
+        track_count: None,
+        ..props("Loose Single", "Somebody", UNKNOWN_ALBUM)
+    };
+    assert_snapshot("no_album", &render_toast(&props, &Layout::default()));
+}
+
+#[test]
+fn snapshot_progress() {
+    let props = SpectreProps {
+        position: Some(std::time::Duration::from_secs(83)),
+        duration: Some(std::time::Duration::from_secs(271)),
+        ..props("Ghost Town", "Kanye West, PARTYNEXTDOOR", "ye")
+    };
+    assert_snapshot("progress", &render_toast(&props, &Layout::default()));
+}
+
+#[test]
+fn snapshot_source() {
+    let props = SpectreProps {
+        source: SourceApp { icon: Some(art("wide.png")), ..SourceApp::from_id("Spotify.exe") },
+        ..props("Ghost Town", "Kanye West, PARTYNEXTDOOR", "ye")
+    };
+    assert_snapshot("source", &render_toast(&props, &Layout::default()));
+}
+
+#[test]
+fn snapshot_blurred() {
+    let layout = Layout { background_style: BackgroundStyle::Blurred, ..Layout::default() };
+    assert_snapshot("blurred", &render_toast(&props("Ghost Town", "Kanye West, PARTYNEXTDOOR", "ye"), &layout));
+}
+
+#[test]
+fn snapshot_rtl() {
+    let props = props("שיר השירים 2", "להקה", "אלבום");
+    assert_snapshot("rtl", &render_toast(&props, &Layout::default()));
+}
+
+#[test]
+fn snapshot_marquee() {
+    let props = props("Pneumonoultramicroscopicsilicovolcanoconiosis (Extended Club Remix)", "Somebody", "Something");
+    let layout = Layout { overflow: Overflow::Marquee, ..Layout::default() };
+    assert!(scrolls(&props, &layout));
+    assert_snapshot("marquee", &render_toast_at(&props, &layout, 4.0));
+    let mut marquee = Marquee::new(&props, &layout).unwrap();
+    // Scrolled somewhere else first, so what's left of the title from before has to go.
+    marquee.at(1.0);
+    assert_snapshot("marquee", &marquee.at(4.0));
+}
+
+#[test]
+fn marquee_matches_rendering_it_all() {
+    let props = props("Pneumonoultramicroscopicsilicovolcanoconiosis (Extended Club Remix)", "Somebody", "Something");
+    let config = crate::config::Config::parse("[toast]\ntheme = \"card\"\n[theme]\noverflow = \"marquee\"").unwrap();
+    let layout = crate::rules::Verdict::default().settings(&config, &props).layout;
+    let mut marquee = Marquee::new(&props, &layout).unwrap();
+    for t in [0.0, 4.0, 2.5, 9.0] {
+        assert!(marquee.at(t) == render_toast_at(&props, &layout, t), "{}", t);
+    }
+    assert!(Marquee::new(&self::props("Short", "Somebody", "Something"), &layout).is_none());
+}
+
+/// How `props` looks in the built in theme `name`, tinted and all.
+fn themed(name: &str, props: &SpectreProps) -> RgbaImage {
+    let config = crate::config::Config::parse(&format!("[toast]\ntheme = \"{}\"", name)).unwrap();
+    render_toast(props, &crate::rules::Verdict::default().settings(&config, props).layout)
+}
+
+#[test]
+fn snapshot_theme_bar() {
+    let props = SpectreProps {
+        position: Some(std::time::Duration::from_secs(83)),
+        duration: Some(std::time::Duration::from_secs(271)),
+        ..props("Ghost Town", "Kanye West, PARTYNEXTDOOR", "ye")
+    };
+    assert_snapshot("theme_bar", &themed("bar", &props));
+}
+
+#[test]
+fn snapshot_theme_card() {
+    let props = SpectreProps {
+        position: Some(std::time::Duration::from_secs(83)),
+        duration: Some(std::time::Duration::from_secs(271)),
+        ..props("Ghost Town", "Kanye West, PARTYNEXTDOOR", "ye")
+    };
+    assert_snapshot("theme_card", &themed("card", &props));
+}
+
+#[test]
+fn snapshot_theme_art() {
+    assert_snapshot("theme_art", &themed("art", &props("Ghost Town", "Kanye West, PARTYNEXTDOOR", "ye")));
+}
+
+#[test]
+fn snapshot_art_rounded() {
+    let layout = Layout { art_radius: 16, art_shadow: 8, ..Layout::default() };
+    assert_snapshot("art_rounded", &render_toast(&props("Ghost Town", "Kanye West, PARTYNEXTDOOR", "ye"), &layout));
+}
+
+#[test]
+fn snapshot_art_vinyl() {
+    let layout = Layout { art_shape: ArtShape::Vinyl, ..Layout::default() };
+    assert_snapshot("art_vinyl", &render_toast(&props("Ghost Town", "Kanye West, PARTYNEXTDOOR", "ye"), &layout));
+}
@@ -0,0 +1,294 @@
+//! Lines of text on a toast.
+//!
+//! Text is drawn in the bundled DejaVu fonts, falling back on the system's fonts for anything they don't have,
+//! see `fonts`. Right-to-left runs are put in display order first, and lines too long for their space either
+//! get cut short with an ellipsis or scroll by as a marquee.
+use super::fonts::{fallback_for, from_file};
+use ab_glyph::{point, Font, FontArc, FontRef, GlyphId, GlyphImageFormat, PxScale, ScaleFont};
+use image::{imageops::FilterType, Rgba, RgbaImage};
+use serde::Deserialize;
+use std::{path::PathBuf, sync::LazyLock};
+use unicode_bidi::BidiInfo;
+
+// Both embedded at compile time, see DejaVu-LICENSE.txt next to them.
+pub static REGULAR: LazyLock<FontRef<'static>> = LazyLock::new(|| {
+    FontRef::try_from_slice(include_bytes!("DejaVuSans.ttf")).expect("Bundled font is broken")
+});
+pub static BOLD: LazyLock<FontRef<'static>> = LazyLock::new(|| {
+    FontRef::try_from_slice(include_bytes!("DejaVuSans-Bold.ttf")).expect("Bundled font is broken")
+});
+
+const ELLIPSIS: char = '…';
+/// Seconds a marquee sits still at the start of every loop, so the start of the text can be read.
+const MARQUEE_PAUSE: f32 = 1.5;
+/// How fast a marquee scrolls, in font sizes a second.
+const MARQUEE_SPEED: f32 = 2.5;
+
+/// What happens to a line of text that's too long for its space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Overflow {
+    /// Cut off at the edge.
+    Clip,
+    /// Cut short with a "…".
+    #[default]
+    Ellipsis,
+    /// Scrolls the whole line by, over and over, see `draw_marquee`.
+    Marquee,
+}
+
+/// Which font a line of text is drawn in: one of the bundled ones, or any font file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub enum FontChoice {
+    Regular,
+    Bold,
+    /// The first font in a .ttf, .otf or .ttc file. `Regular` if it won't load.
+    File(PathBuf),
+}
+impl From<String> for FontChoice {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "regular" => FontChoice::Regular,
+            "bold" => FontChoice::Bold,
+            _ => FontChoice::File(PathBuf::from(name)),
+        }
+    }
+}
+impl FontChoice {
+    pub fn font(&self) -> &'static FontRef<'static> {
+        match self {
+            FontChoice::Regular => &REGULAR,
+            FontChoice::Bold => &BOLD,
+            FontChoice::File(path) => from_file(path).unwrap_or(&REGULAR),
+        }
+    }
+}
+
+/// How a line of text should look.
+#[derive(Clone, Copy)]
+pub struct TextStyle {
+    pub font: &'static FontRef<'static>,
+    /// Height of the font in pixels.
+    pub size: f32,
+    pub color: Rgba<u8>,
+}
+impl TextStyle {
+    /// Distance from the top of a line to the top of the next one.
+    pub fn line_height(&self) -> f32 {
+        let font = self.font.as_scaled(PxScale::from(self.size));
+        font.ascent() - font.descent() + font.line_gap()
+    }
+}
+
+/// A glyph laid out on a line, in whichever font had it.
+struct Placed {
+    font: FontArc,
+    id: GlyphId,
+    x: f32,
+}
+
+/// Puts `text` in the order it's shown in, reversing right-to-left runs. There's no shaping, so Arabic letters
+/// keep their isolated forms.
+pub fn visual_order(text: &str) -> String {
+    let bidi = BidiInfo::new(text, None);
+    if !bidi.has_rtl() {
+        return text.to_string();
+    }
+    bidi.paragraphs.iter().map(|para| bidi.reorder_line(para, para.range.clone())).collect()
+}
+
+/// Lays out `text`, already in visual order, on a single line starting at x = 0, returning each glyph with its
+/// x position and the total width.
+fn layout_line(text: &str, style: &TextStyle) -> (Vec<Placed>, f32) {
+    let scale = PxScale::from(style.size);
+    let primary = FontArc::from(style.font.clone());
+    let mut glyphs = Vec::new();
+    let mut x = 0.0;
+    // Kerning only means anything between two glyphs of the same font, so only the primary one gets it.
+    let mut last: Option<GlyphId> = None;
+    for c in text.chars() {
+        // Joiners and emoji presentation selectors don't draw anything, and there's no shaping to use them for.
+        if c.is_control() || matches!(c, '\u{200D}' | '\u{FE0E}' | '\u{FE0F}') {
+            continue;
+        }
+        let in_primary = style.font.glyph_id(c).0 != 0;
+        let font = if in_primary { primary.clone() } else { fallback_for(c).unwrap_or(primary.clone()) };
+        let scaled = font.as_scaled(scale);
+        let id = scaled.glyph_id(c);
+        if let (Some(last), true) = (last, in_primary) {
+            x += scaled.kern(last, id);
+        }
+        glyphs.push(Placed { font: font.clone(), id, x });
+        x += scaled.h_advance(id);
+        last = in_primary.then_some(id);
+    }
+    (glyphs, x)
+}
+
+/// Width of `text` on one line, in pixels.
+pub fn measure(text: &str, style: &TextStyle) -> f32 {
+    layout_line(&visual_order(text), style).1
+}
+
+/// `text` cut short with an ellipsis so it fits in `max_width` pixels, or as is if it already does.
+pub fn ellipsize(text: &str, style: &TextStyle, max_width: u32) -> String {
+    let fits = |text: &str| measure(text, style) <= max_width as f32;
+    if fits(text) {
+        return text.to_string();
+    }
+    let chars: Vec<char> = text.chars().collect();
+    let cut = |len: usize| format!("{}{}", chars[..len].iter().collect::<String>().trim_end(), ELLIPSIS);
+    // The longest start of the text that fits with the ellipsis on the end.
+    let (mut fitting, mut too_long) = (0, chars.len());
+    while too_long - fitting > 1 {
+        let mid = (fitting + too_long) / 2;
+        if fits(&cut(mid)) { fitting = mid } else { too_long = mid }
+    }
+    cut(fitting)
+}
+
+/// Blends `color` onto `dst` with `coverage` (0 to 1) of it showing, using the usual "over" operator.
+pub fn blend(dst: &mut Rgba<u8>, color: Rgba<u8>, coverage: f32) {
+    let src_a = color.0[3] as f32 / 255.0 * coverage.clamp(0.0, 1.0);
+    if src_a <= 0.0 {
+        return;
+    }
+    let dst_a = dst.0[3] as f32 / 255.0;
+    let out_a = src_a + dst_a * (1.0 - src_a);
+    for i in 0..3 {
+        let c = (color.0[i] as f32 * src_a + dst.0[i] as f32 * dst_a * (1.0 - src_a)) / out_a;
+        dst.0[i] = c.round() as u8;
+    }
+    dst.0[3] = (out_a * 255.0).round() as u8;
+}
+
+/// Draws laid out glyphs with the line's left end at `x` and its top at `y`, leaving out anything outside the
+/// `clip` range of x.
+fn draw_glyphs(canvas: &mut RgbaImage, glyphs: &[Placed], x: f32, y: i32, clip: (i32, i32), style: &TextStyle) {
+    let scale = PxScale::from(style.size);
+    let baseline = y as f32 + style.font.as_scaled(scale).ascent();
+    let (width, height) = (canvas.width() as i32, canvas.height() as i32);
+    for glyph in glyphs {
+        let gx = x + glyph.x;
+        if gx >= clip.1 as f32 {
+            break;
+        }
+        let positioned = glyph.id.with_scale_and_position(scale, point(gx, baseline));
+        let Some(outlined) = glyph.font.outline_glyph(positioned) else {
+            draw_image_glyph(canvas, glyph, gx, baseline, clip, style);
+            continue;
+        };
+        let bounds = outlined.px_bounds();
+        outlined.draw(|px, py, coverage| {
+            let cx = bounds.min.x as i32 + px as i32;
+            let cy = bounds.min.y as i32 + py as i32;
+            if cx < clip.0.max(0) || cx >= clip.1.min(width) || cy < 0 || cy >= height {
+                return;
+            }
+            blend(canvas.get_pixel_mut(cx as u32, cy as u32), style.color, coverage);
+        });
+    }
+}
+
+/// Draws a glyph that's a picture rather than an outline, like a color emoji. Only PNG ones are supported.
+fn draw_image_glyph(canvas: &mut RgbaImage, glyph: &Placed, x: f32, baseline: f32, clip: (i32, i32), style: &TextStyle) {
+    let em = glyph.font.as_scaled(PxScale::from(style.size)).h_scale_factor() * glyph.font.units_per_em().unwrap_or(1.0);
+    let Some(image) = glyph.font.glyph_raster_image2(glyph.id, em.round() as u16) else { return };
+    if !matches!(image.format, GlyphImageFormat::Png) {
+        return;
+    }
+    let Ok(png) = image::load_from_memory(image.data) else { return };
+    // Strikes only come in a few sizes, so it's scaled from whichever was closest.
+    let factor = em / image.pixels_per_em.max(1) as f32;
+    let size = |px: u32| (px as f32 * factor).round().max(1.0) as u32;
+    let png = png.resize_exact(size(png.width()), size(png.height()), FilterType::Triangle).to_rgba8();
+    // The image's origin is its bottom left corner, up from the baseline.
+    let left = (x + image.origin.x * factor).round() as i32;
+    let top = (baseline - image.origin.y * factor).round() as i32 - png.height() as i32;
+    for (px, py, pixel) in png.enumerate_pixels() {
+        let (cx, cy) = (left + px as i32, top + py as i32);
+        if cx < clip.0.max(0) || cx >= clip.1.min(canvas.width() as i32) || cy < 0 || cy >= canvas.height() as i32 {
+            continue;
+        }
+        blend(canvas.get_pixel_mut(cx as u32, cy as u32), *pixel, 1.0);
+    }
+}
+
+/// Draws a single line of text with the top of the line at (`x`, `y`), fitting it in `max_width` pixels the way
+/// `overflow` says. `Marquee` is drawn as it is before it starts scrolling, see `draw_marquee` for the rest.
+///
+/// # Returns
+/// The width of the text that was drawn.
+pub fn draw_text(canvas: &mut RgbaImage, text: &str, x: i32, y: i32, max_width: u32, style: &TextStyle,
+    overflow: Overflow) -> u32 {
+    let text = match overflow {
+        Overflow::Ellipsis => ellipsize(text, style, max_width),
+        Overflow::Clip | Overflow::Marquee => text.to_string(),
+    };
+    let (glyphs, width) = layout_line(&visual_order(&text), style);
+    draw_glyphs(canvas, &glyphs, x as f32, y, (x, x + max_width as i32), style);
+    (width.ceil() as u32).min(max_width)
+}
+
+/// How far a marquee `loop_width` pixels around has scrolled `t` seconds in, going `speed` pixels a second.
+/// It sits still for a moment, scrolls all the way round, and starts over.
+pub fn marquee_offset(t: f32, loop_width: f32, speed: f32) -> f32 {
+    let cycle = MARQUEE_PAUSE + loop_width / speed;
+    let t = t.max(0.0) % cycle;
+    ((t - MARQUEE_PAUSE) * speed).clamp(0.0, loop_width)
+}
+
+/// Like `draw_text`, but text too long for `max_width` scrolls by as a marquee, `t` seconds into it.
+/// The end of the text is followed by a gap and then its start again, so it loops round seamlessly.
+pub fn draw_marquee(canvas: &mut RgbaImage, text: &str, x: i32, y: i32, max_width: u32, style: &TextStyle, t: f32) -> u32 {
+    let (glyphs, width) = layout_line(&visual_order(text), style);
+    let clip = (x, x + max_width as i32);
+    if width <= max_width as f32 {
+        draw_glyphs(canvas, &glyphs, x as f32, y, clip, style);
+        return width.ceil() as u32;
+    }
+    let loop_width = width + style.size * 2.0;
+    let start = x as f32 - marquee_offset(t, loop_width, style.size * MARQUEE_SPEED);
+    draw_glyphs(canvas, &glyphs, start, y, clip, style);
+    draw_glyphs(canvas, &glyphs, start + loop_width, y, clip, style);
+    max_width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style() -> TextStyle {
+        TextStyle { font: &REGULAR, size: 16.0, color: Rgba([255, 255, 255, 255]) }
+    }
+
+    #[test]
+    fn ellipsizes_what_doesnt_fit() {
+        let style = style();
+        assert_eq!(ellipsize("Short", &style, 200), "Short");
+        let cut = ellipsize("A title that goes on for far too long", &style, 120);
+        assert!(cut.ends_with(ELLIPSIS) && !cut.ends_with(" …"), "{}", cut);
+        assert!(measure(&cut, &style) <= 120.0);
+        assert!(cut.len() > 10, "{}", cut);
+        assert!("A title that goes on for far too long".starts_with(cut.trim_end_matches(ELLIPSIS)));
+    }
+
+    #[test]
+    fn puts_rtl_in_display_order() {
+        assert_eq!(visual_order("Hello"), "Hello");
+        assert_eq!(visual_order("שלום"), "םולש");
+        // The number stays the right way round in the middle of the Hebrew.
+        assert_eq!(visual_order("שיר 12 א"), "א 12 ריש");
+    }
+
+    #[test]
+    fn marquee_pauses_then_loops() {
+        assert_eq!(marquee_offset(0.0, 100.0, 50.0), 0.0);
+        assert_eq!(marquee_offset(MARQUEE_PAUSE, 100.0, 50.0), 0.0);
+        assert_eq!(marquee_offset(MARQUEE_PAUSE + 1.0, 100.0, 50.0), 50.0);
+        // Round once in 2 seconds, then back to the start.
+        assert_eq!(marquee_offset(MARQUEE_PAUSE + 2.0, 100.0, 50.0), 0.0);
+        assert_eq!(marquee_offset(2.0 * MARQUEE_PAUSE + 2.5, 100.0, 50.0), 25.0);
+    }
+}
@@ -0,0 +1,301 @@
+//! Rules for which tracks get toasted and how, from the `[[rule]]`s in the config.
+//!
+//! A rule is some conditions and what to do when all of them match. Leaving a condition out matches anything.
+//! ```toml
+//! # Muted autoplay in browsers is never worth a toast
+//! [[rule]]
+//! app = "chrome"
+//! type = "video"
+//! action = "deny"
+//!
+//! # ...unless it's a music video
+//! [[rule]]
+//! app = "chrome"
+//! title = "(?i)official (music )?video"
+//! action = "allow"
+//!
+//! # Podcasts get a quick, dark toast
+//! [[rule]]
+//! genre = "podcast"
+//! fade_out = 2.0
+//! theme = { background = "#202020", title_color = "#ffcc00" }
+//! ```
+//! Rules are checked in order and later ones win: the last matching `action` decides, and overrides pile up with later ones on top.
+//! `allow` shows the toast even if the config's `[filters]` would have hidden it.
+use crate::anim::{Animation, Timeline};
+use crate::config::{opt_hex_color, Config};
+use crate::props::*;
+use crate::render::{BackgroundStyle, Layout};
+use image::Rgba;
+use regex::Regex;
+use serde::{de::Error as _, Deserialize, Deserializer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Toast it, filters or not.
+    Allow,
+    /// Never toast it.
+    Deny,
+}
+
+/// A title regex. Compiled once, when the config is read.
+#[derive(Debug, Clone)]
+pub struct TitlePattern(pub Regex);
+impl PartialEq for TitlePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+impl<'de> Deserialize<'de> for TitlePattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Regex::new(&String::deserialize(deserializer)?).map(TitlePattern).map_err(D::Error::custom)
+    }
+}
+
+/// The bits of the `[theme]` a rule can change. Anything left out stays as the theme has it.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeOverride {
+    #[serde(deserialize_with = "opt_hex_color")]
+    pub background: Option<Rgba<u8>>,
+    #[serde(deserialize_with = "opt_hex_color")]
+    pub title_color: Option<Rgba<u8>>,
+    #[serde(deserialize_with = "opt_hex_color")]
+    pub text_color: Option<Rgba<u8>>,
+    #[serde(deserialize_with = "opt_hex_color")]
+    pub progress_color: Option<Rgba<u8>>,
+    pub title_size: Option<f32>,
+    pub text_size: Option<f32>,
+    pub tint: Option<Tint>,
+    pub style: Option<BackgroundStyle>,
+    pub art_shape: Option<ArtShape>,
+}
+impl ThemeOverride {
+    /// Puts `other` on top of this, anything it sets wins.
+    fn merge(&mut self, other: &ThemeOverride) {
+        self.background = other.background.or(self.background);
+        self.title_color = other.title_color.or(self.title_color);
+        self.text_color = other.text_color.or(self.text_color);
+        self.progress_color = other.progress_color.or(self.progress_color);
+        self.title_size = other.title_size.or(self.title_size);
+        self.text_size = other.text_size.or(self.text_size);
+        self.tint = other.tint.or(self.tint);
+        self.style = other.style.or(self.style);
+        self.art_shape = other.art_shape.or(self.art_shape);
+    }
+
+    fn apply(&self, layout: &mut Layout) {
+        layout.background = self.background.unwrap_or(layout.background);
+        layout.title_color = self.title_color.unwrap_or(layout.title_color);
+        layout.text_color = self.text_color.unwrap_or(layout.text_color);
+        layout.progress_color = self.progress_color.unwrap_or(layout.progress_color);
+        layout.title_size = self.title_size.unwrap_or(layout.title_size);
+        layout.text_size = self.text_size.unwrap_or(layout.text_size);
+        layout.background_style = self.style.unwrap_or(layout.background_style);
+        layout.art_shape = self.art_shape.unwrap_or(layout.art_shape);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rule {
+    /// Matched against the session id and the app's id and name, ignoring case.
+    pub app: Option<String>,
+    #[serde(rename = "type")]
+    pub playback_type: Option<SPT>,
+    pub title: Option<TitlePattern>,
+    /// Any of the track's genres, ignoring case.
+    pub genre: Option<String>,
+
+    pub action: Option<Action>,
+    pub fade_out: Option<f32>,
+    pub opacity: Option<u8>,
+    pub theme: ThemeOverride,
+}
+
+impl Rule {
+    pub fn matches(&self, session: &str, props: &SpectreProps) -> bool {
+        let app = self.app.as_ref().is_none_or(|app| {
+            session.to_lowercase().contains(&app.to_lowercase()) || props.source.matches(app)
+        });
+        let playback_type = self.playback_type.is_none_or(|t| t == props.playback_type);
+        let title = self.title.as_ref().is_none_or(|title| title.0.is_match(&props.title));
+        let genre = self.genre.as_ref().is_none_or(|genre| props.genres.iter().any(|g| g.eq_ignore_ascii_case(genre)));
+        app && playback_type && title && genre
+    }
+}
+
+/// What the rules had to say about a track.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Verdict {
+    pub action: Option<Action>,
+    pub fade_out: Option<f32>,
+    pub opacity: Option<u8>,
+    pub theme: ThemeOverride,
+}
+
+impl Verdict {
+    /// Checks every rule against a track, later rules winning over earlier ones.
+    pub fn of(rules: &[Rule], session: &str, props: &SpectreProps) -> Self {
+        let mut verdict = Verdict::default();
+        for rule in rules.iter().filter(|rule| rule.matches(session, props)) {
+            verdict.action = rule.action.or(verdict.action);
+            verdict.fade_out = rule.fade_out.or(verdict.fade_out);
+            verdict.opacity = rule.opacity.or(verdict.opacity);
+            verdict.theme.merge(&rule.theme);
+        }
+        verdict
+    }
+
+    /// Whether the track gets a toast. Without an `action` that's up to the config's filters.
+    pub fn shows(&self, config: &Config, session: &str, props: &SpectreProps) -> bool {
+        match self.action {
+            Some(Action::Allow) => true,
+            Some(Action::Deny) => false,
+            None => config.filters.allows(session, props),
+        }
+    }
+
+    /// The config's toast settings for `props`, tinted if the theme says so, with this verdict's overrides on top.
+    pub fn settings(&self, config: &Config, props: &SpectreProps) -> ToastSettings {
+        let mut settings = ToastSettings::from(config);
+        settings.fade_out = self.fade_out.unwrap_or(settings.fade_out);
+        settings.opacity = self.opacity.unwrap_or(settings.opacity);
+        let tint = match (self.theme.tint.unwrap_or(config.theme.tint), self.theme.style.unwrap_or(config.theme.style)) {
+            (Tint::None, BackgroundStyle::Palette) => Tint::Dominant,
+            (tint, _) => tint,
+        };
+        if tint != Tint::None {
+            settings.layout = settings.layout.tinted(&props.palette(), tint);
+        }
+        self.theme.apply(&mut settings.layout);
+        settings
+    }
+}
+
+/// Everything about how one toast looks and behaves, once the rules have had their say.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToastSettings {
+    /// Seconds it takes to fade out.
+    pub fade_out: f32,
+    pub opacity: u8,
+    pub layout: Layout,
+    pub animation: Animation,
+}
+impl From<&Config> for ToastSettings {
+    fn from(config: &Config) -> Self {
+        ToastSettings {
+            fade_out: config.toast.fade_out,
+            opacity: config.toast.opacity,
+            layout: config.layout(),
+            animation: config.animation.clone(),
+        }
+    }
+}
+impl ToastSettings {
+    pub fn timeline(&self) -> Timeline {
+        Timeline { animation: self.animation.clone(), fade_out: self.fade_out, opacity: self.opacity }
+    }
+
+    /// The same settings with every size multiplied by `scale`, see `Layout::scaled`.
+    pub fn scaled(&self, scale: f32) -> ToastSettings {
+        let mut scaled = self.clone();
+        scaled.layout = self.layout.scaled(scale);
+        scaled.animation.slide_distance = (self.animation.slide_distance as f32 * scale).round() as u32;
+        scaled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config::parse(r##"
+            [filters]
+            ignore_types = ["video"]
+
+            [[rule]]
+            app = "chrome"
+            action = "deny"
+
+            [[rule]]
+            app = "chrome"
+            title = "(?i)official (music )?video"
+            action = "allow"
+
+            [[rule]]
+            genre = "podcast"
+            fade_out = 2.0
+            theme = { background = "#202020", art_shape = "circle" }
+
+            [[rule]]
+            genre = "Podcast"
+            type = "audio"
+            theme = { title_color = "#ffcc00" }
+        "##).unwrap()
+    }
+
+    fn track(title: &str, playback_type: SPT, genre: &str) -> SpectreProps {
+        SpectreProps {
+            title: title.to_string(),
+            playback_type,
+            genres: vec![genre.to_string()],
+            ..SpectreProps::new()
+        }
+    }
+
+    #[test]
+    fn later_actions_win() {
+        let config = config();
+        let shows = |session: &str, props: &SpectreProps| Verdict::of(&config.rules, session, props).shows(&config, session, props);
+        assert!(!shows("chrome", &track("Autoplay Ad", SPT::AUDIO, "")));
+        // Allowed even though the filters hide videos.
+        assert!(shows("chrome", &track("Band - Song (Official Music Video)", SPT::VIDEO, "")));
+        assert!(!shows("vlc", &track("Some Film", SPT::VIDEO, "")));
+        assert!(shows("vlc", &track("Some Song", SPT::AUDIO, "")));
+    }
+
+    #[test]
+    fn overrides_pile_up() {
+        let config = config();
+        let podcast = track("Episode 12", SPT::AUDIO, "podcast");
+        let settings = Verdict::of(&config.rules, "spotify", &podcast).settings(&config, &podcast);
+        assert_eq!(settings.fade_out, 2.0);
+        assert_eq!(settings.opacity, config.toast.opacity);
+        assert_eq!(settings.layout.background, Rgba([0x20, 0x20, 0x20, 255]));
+        assert_eq!(settings.layout.title_color, Rgba([0xff, 0xcc, 0x00, 255]));
+        assert_eq!(settings.layout.text_color, config.layout().text_color);
+        assert_eq!(settings.layout.art_shape, ArtShape::Circle);
+
+        let song = track("Ghost Town", SPT::AUDIO, "Hip Hop");
+        assert_eq!(Verdict::of(&config.rules, "spotify", &song).settings(&config, &song), ToastSettings::from(&config));
+    }
+
+    #[test]
+    fn tints_to_the_art() {
+        let config = Config::parse("[theme]\ntint = \"dominant\"\n[[rule]]\ngenre = \"podcast\"\ntheme = { tint = \"none\" }").unwrap();
+        let mut song = track("Yellow", SPT::AUDIO, "Rock");
+        song.thumbnail = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(8, 8, Rgba([250, 220, 40, 255])));
+        let layout = Verdict::of(&config.rules, "spotify", &song).settings(&config, &song).layout;
+        assert_eq!(layout.background, Rgba([250, 220, 40, config.theme.background[3]]));
+        // White doesn't read on yellow.
+        assert_eq!(layout.title_color, Rgba([0, 0, 0, 255]));
+
+        // A palette background is tinted without being told which color.
+        let palette = Config::parse("[theme]\nstyle = \"palette\"").unwrap();
+        let layout = Verdict::default().settings(&palette, &song).layout;
+        assert_eq!(layout.background, Rgba([250, 220, 40, config.theme.background[3]]));
+
+        let podcast = SpectreProps { genres: vec!["podcast".to_string()], ..song };
+        assert_eq!(Verdict::of(&config.rules, "spotify", &podcast).settings(&config, &podcast), ToastSettings::from(&config));
+    }
+
+    #[test]
+    fn bad_rules_are_errors() {
+        assert!(Config::parse("[[rule]]\ntitle = \"(unclosed\"").is_err());
+        assert!(Config::parse("[[rule]]\naction = \"maybe\"").is_err());
+        assert!(Config::parse("[[rule]]\ntheme = { font = \"Comic Sans\" }").is_err());
+    }
+}
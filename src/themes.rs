@@ -0,0 +1,123 @@
+//! Themes: whole looks for a toast, in TOML files of their own so they can be shared and switched between.
+//!
+//! A theme has the same `[size]`, `[theme]` and `[elements]` sections as the config, and `[toast] theme = "..."`
+//! starts the config off from one. Anything the config sets itself still wins, so a theme can be tweaked without
+//! copying all of it:
+//! ```toml
+//! [toast]
+//! theme = "card"
+//! [elements.title]
+//! size = 26.0        # the rest of the title's box stays where the card put it
+//! ```
+//! There are a few built in themes:
+//! - `bar`: a slim bar with small art, the title and the artist.
+//! - `card`: a big card in the art's colors, with the art on top and the text centered under it.
+//! - `art`: nothing but the art.
+//!
+//! Anything else is a file: `<name>.toml` in the `themes` dir next to the config, or any path ending in `.toml`.
+use crate::config::Config;
+use std::{fs, io::{Error, ErrorKind}, path::PathBuf, result::Result};
+use toml::{Table, Value};
+
+const BUILT_IN: [(&str, &str); 3] = [
+    ("bar", include_str!("themes/bar.toml")),
+    ("card", include_str!("themes/card.toml")),
+    ("art", include_str!("themes/art.toml")),
+];
+
+/// The only parts of a config a theme gets to set.
+const SECTIONS: [&str; 3] = ["size", "theme", "elements"];
+
+/// Where theme files are looked for by name.
+pub fn dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("song_spectre").join("themes"))
+}
+
+fn path(name: &str) -> Option<PathBuf> {
+    if name.ends_with(".toml") {
+        return Some(PathBuf::from(name));
+    }
+    dir().map(|dir| dir.join(format!("{}.toml", name)))
+}
+
+/// The theme called `name`, as the table a config goes on top of.
+pub fn load(name: &str) -> Result<Table, Error> {
+    let error = |kind: ErrorKind, e: &dyn std::fmt::Display| Error::new(kind, format!("Theme {}: {}", name, e));
+    let text = match BUILT_IN.iter().find(|(built_in, _)| *built_in == name) {
+        Some((_, text)) => text.to_string(),
+        None => {
+            let path = path(name).ok_or_else(|| error(ErrorKind::NotFound, &"there's no config dir to look in"))?;
+            fs::read_to_string(&path).map_err(|e| error(e.kind(), &format!("{}: {}", path.display(), e)))?
+        }
+    };
+    let theme: Table = toml::from_str(&text).map_err(|e| error(ErrorKind::InvalidData, &e))?;
+    if let Some(key) = theme.keys().find(|key| !SECTIONS.contains(&key.as_str())) {
+        return Err(error(ErrorKind::InvalidData, &format!("a theme can only have [size], [theme] and [elements], not {}", key)));
+    }
+    // Checked on its own, so mistakes in it get blamed on the theme rather than on the config.
+    Value::Table(theme.clone()).try_into::<Config>().map_err(|e| error(ErrorKind::InvalidData, &e))?;
+    Ok(theme)
+}
+
+/// Puts `config` on top of `theme`. Tables get merged all the way down, anything else the config has replaces the theme's.
+pub fn apply(mut theme: Table, config: Table) -> Table {
+    for (key, value) in config {
+        match (theme.get_mut(&key), value) {
+            (Some(Value::Table(under)), Value::Table(over)) => *under = apply(std::mem::take(under), over),
+            (_, value) => {
+                theme.insert(key, value);
+            }
+        }
+    }
+    theme
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::Align;
+
+    #[test]
+    fn built_in_themes() {
+        for (name, _) in BUILT_IN {
+            let config = Config::parse(&format!("[toast]\ntheme = \"{}\"", name)).unwrap();
+            assert!(config.layout().elements.is_some(), "{}", name);
+        }
+        let card = Config::parse("[toast]\ntheme = \"card\"").unwrap().layout();
+        assert_eq!((card.width, card.height, card.radius, card.shadow), (320, 420, 16, 12));
+        assert!(Config::parse("[toast]\ntheme = \"no such theme\"").is_err());
+    }
+
+    #[test]
+    fn config_goes_on_top() {
+        let config = Config::parse(r##"
+            [toast]
+            theme = "card"
+            [size]
+            radius = 4
+            [elements.title]
+            size = 26.0
+        "##).unwrap();
+        let layout = config.layout();
+        assert_eq!((layout.width, layout.radius), (320, 4));
+        let title = layout.elements.unwrap().title.unwrap();
+        assert_eq!((title.x, title.size, title.align), (24, Some(26.0), Align::Center));
+        assert_eq!(config.toast.theme.as_deref(), Some("card"));
+    }
+
+    #[test]
+    fn theme_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("mine.toml");
+        fs::write(&file, "[size]\nwidth = 200\n[elements]\nart = { x = 0, y = 0, width = 200, height = 160 }").unwrap();
+        let config = Config::parse(&format!("[toast]\ntheme = {:?}", file.to_str().unwrap())).unwrap();
+        assert_eq!(config.layout().width, 200);
+        assert_eq!(config.layout().elements.unwrap().art.unwrap().height, 160);
+
+        fs::write(&file, "[toast]\nfade_out = 1.0").unwrap();
+        assert!(load(file.to_str().unwrap()).is_err());
+        fs::write(&file, "[theme]\nbackground = \"grey\"").unwrap();
+        let error = load(file.to_str().unwrap()).unwrap_err().to_string();
+        assert!(error.starts_with("Theme "), "{}", error);
+    }
+}
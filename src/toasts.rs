@@ -0,0 +1,185 @@
+//! Where toasts go when there's more than one.
+//!
+//! `ToastManager` only does the bookkeeping: which toasts are on screen and where, which are waiting their turn,
+//! and which got replaced. Whatever draws the toasts asks it for each toast's `Slot` and follows along.
+use crate::config::{Config, Position};
+use crate::placement::{self, Monitor};
+use std::collections::VecDeque;
+
+pub type ToastId = u64;
+
+/// Where a toast stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    /// Waiting for a spot on screen.
+    Queued,
+    /// On screen, with its top left corner here.
+    Shown((i32, i32)),
+    /// Done with, replaced by a newer toast for its session, or never was. Whatever shows it should close it.
+    Gone,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    id: ToastId,
+    session: String,
+    size: (u32, u32),
+}
+
+/// Stacks toasts out from the configured anchor, newest nearest it, with at most `max` of them on screen.
+///
+/// A new toast for a session that already has one takes over its spot (or its place in the queue),
+/// so a track change doesn't shuffle everything around.
+#[derive(Debug)]
+pub struct ToastManager {
+    position: Position,
+    monitor: Monitor,
+    max: usize,
+    spacing: u32,
+    /// Nearest the anchor first.
+    shown: Vec<Entry>,
+    queue: VecDeque<Entry>,
+    next_id: ToastId,
+}
+
+impl ToastManager {
+    /// A manager for toasts on `monitor`, set up by the current config.
+    pub fn new(monitor: Monitor) -> Self {
+        let mut manager = ToastManager {
+            position: Position::default(),
+            monitor,
+            max: 1,
+            spacing: 0,
+            shown: Vec::new(),
+            queue: VecDeque::new(),
+            next_id: 0,
+        };
+        manager.configure(&crate::config::get());
+        manager
+    }
+
+    /// Picks up `config`'s position and stacking settings. Toasts already on screen move to match.
+    pub fn configure(&mut self, config: &Config) {
+        self.position = config.position.clone();
+        self.max = config.toast.max.max(1);
+        self.spacing = config.toast.spacing;
+        self.fill();
+    }
+
+    /// Moves every toast over to `monitor`.
+    pub fn set_monitor(&mut self, monitor: Monitor) {
+        self.monitor = monitor;
+    }
+
+    /// Adds a `size` toast for `session`.
+    pub fn push(&mut self, session: &str, size: (u32, u32)) -> ToastId {
+        let id = self.next_id;
+        self.next_id += 1;
+        let entry = Entry { id, session: session.to_string(), size };
+        if let Some(old) = self.shown.iter_mut().chain(self.queue.iter_mut()).find(|old| old.session == session) {
+            *old = entry;
+        } else if self.shown.len() < self.max {
+            self.shown.insert(0, entry);
+        } else {
+            self.queue.push_back(entry);
+        }
+        id
+    }
+
+    /// Forgets a toast once it's gone from the screen, making room for the next one.
+    pub fn remove(&mut self, id: ToastId) {
+        self.shown.retain(|entry| entry.id != id);
+        self.queue.retain(|entry| entry.id != id);
+        self.fill();
+    }
+
+    /// Moves queued toasts onto the screen while there's room.
+    fn fill(&mut self) {
+        while self.shown.len() < self.max {
+            match self.queue.pop_front() {
+                Some(entry) => self.shown.insert(0, entry),
+                None => break,
+            }
+        }
+    }
+
+    pub fn slot(&self, id: ToastId) -> Slot {
+        if self.queue.iter().any(|entry| entry.id == id) {
+            return Slot::Queued;
+        }
+        let spacing = (self.spacing as f32 * placement::scale(&self.position, &self.monitor)).round() as u32;
+        let mut offset = 0;
+        for entry in &self.shown {
+            if entry.id == id {
+                let (x, y) = placement::place(&self.position, &self.monitor, entry.size);
+                return Slot::Shown((x, if self.position.stacks_up() { y - offset } else { y + offset }));
+            }
+            offset += (entry.size.1 + spacing) as i32;
+        }
+        Slot::Gone
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager(config: &str) -> ToastManager {
+        let mut manager = ToastManager::new(Monitor::single((1920, 1080)));
+        manager.configure(&Config::parse(config).unwrap());
+        manager
+    }
+
+    #[test]
+    fn stacks_away_from_the_anchor() {
+        let mut up = manager("toast = { spacing = 10 }\nposition = { margin = [20, 20] }");
+        let first = up.push("spotify", (400, 100));
+        let second = up.push("browser", (400, 150));
+        assert_eq!(up.slot(second), Slot::Shown((1500, 910)));
+        assert_eq!(up.slot(first), Slot::Shown((1500, 800)));
+
+        let mut down = manager("toast = { spacing = 10 }\nposition = { anchor = \"top\", margin = [20, 20] }");
+        let first = down.push("spotify", (400, 100));
+        let second = down.push("browser", (400, 150));
+        assert_eq!(down.slot(second), Slot::Shown((760, 20)));
+        assert_eq!(down.slot(first), Slot::Shown((760, 180)));
+    }
+
+    #[test]
+    fn replaces_toasts_for_the_same_session() {
+        let mut manager = manager("");
+        let spotify = manager.push("spotify", (400, 100));
+        let browser = manager.push("browser", (400, 100));
+        let before = manager.slot(spotify);
+        let next = manager.push("spotify", (400, 100));
+        assert_eq!(manager.slot(spotify), Slot::Gone);
+        assert_eq!(manager.slot(next), before);
+        // The old one closing doesn't disturb anything.
+        let browser_at = manager.slot(browser);
+        manager.remove(spotify);
+        assert_eq!(manager.slot(browser), browser_at);
+        assert_eq!(manager.slot(next), before);
+    }
+
+    #[test]
+    fn queues_past_the_cap() {
+        let mut manager = manager("toast = { max = 2 }");
+        let a = manager.push("a", (400, 100));
+        let b = manager.push("b", (400, 100));
+        let c = manager.push("c", (400, 100));
+        let d = manager.push("d", (400, 100));
+        assert_eq!(manager.slot(c), Slot::Queued);
+        // Replacing a queued toast keeps its place in line.
+        let c2 = manager.push("c", (400, 100));
+        assert_eq!(manager.slot(c), Slot::Gone);
+
+        let a_at = manager.slot(a);
+        manager.remove(b);
+        assert_eq!(manager.slot(a), a_at);
+        assert!(matches!(manager.slot(c2), Slot::Shown(_)));
+        assert_eq!(manager.slot(d), Slot::Queued);
+        manager.remove(a);
+        manager.remove(c2);
+        assert_eq!(manager.slot(d), Slot::Shown((1496, 956)));
+    }
+}
@@ -1,8 +1,15 @@
 use std::thread;
 use std::time::Duration;
 pub mod debug;
+#[cfg(all(test, target_os = "linux"))]
+pub mod test_bus;
+#[cfg(all(test, target_os = "linux"))]
+pub mod test_display;
+#[cfg(all(test, target_os = "linux"))]
+pub mod test_compositor;
 
 
+#[cfg_attr(not(windows), allow(dead_code))]
 pub fn slp (secs: f32) {
     thread::sleep(Duration::from_secs_f32(secs));
 } 
\ No newline at end of file
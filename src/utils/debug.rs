@@ -1,9 +1,15 @@
 //debug functions, make sure to remove or exclude before making release build.
+#![allow(dead_code)]
 
 // FIND A WAY TO MARK WHOLE MOD AS DEBUG NOT JUST THE FUNCTIONS
 use std::{ fs, io::{Cursor, Error}, process::{Command, Stdio}, result::Result, env};
 use base64::{engine::general_purpose::STANDARD, Engine as _};
-use crate::{ghoast::*, props::*};
+use crate::props::*;
+#[cfg(windows)]
+use crate::ghoast::*;
+#[cfg(windows)]
+use crate::rules::ToastSettings;
+#[cfg(windows)]
 use windows::Win32::{Foundation::GetLastError, 
     Graphics::Gdi::GetObjectW};
 //shows a DynamicImage in a browser window and returns a string of the file location.
@@ -63,7 +69,7 @@ pub(crate)  fn view_image(img: Option<&DynamicImage>, title: &str) -> Result<Str
 #[allow(dead_code)]
 #[cfg(debug_assertions)]
 pub(crate) fn sim_error() -> Result<(), std::io::Error> {
-    Err(std::io::Error::new(std::io::ErrorKind::Other, "Simulated failure"))
+    Err(std::io::Error::other("Simulated failure"))
 }
 #[cfg(debug_assertions)]
 pub(crate) fn display_spec_props(spec_props: &SpectreProps) -> Result<(), Error> {
@@ -82,9 +88,9 @@ pub(crate) fn cls() {
         .output();
 }
 
-#[cfg(debug_assertions)]
-pub(crate)  fn show_ghoast(title: &str, props: SpectreProps) -> Ghoast {
-    let toast_window = Ghoast::new(title, props);
+#[cfg(all(debug_assertions, windows))]
+pub(crate)  fn show_ghoast(title: &str, props: SpectreProps, settings: ToastSettings, origin: (i32, i32)) -> Ghoast {
+    let toast_window = Ghoast::new(title, props, settings, origin);
     toast_window.init();
     print!("new {:?}", toast_window.c_name);
     print!(" named {:?}", toast_window.title);
@@ -117,11 +123,12 @@ impl DbgStringExt for String {
     }
     fn push_ln_in(&mut self, str: &str, indent: u8) {
         if !self.is_empty() {
-            self.push_str("\n");
+            self.push('\n');
         }
         self.push_str(&str.indent(indent));
     }
 }
+#[cfg(windows)]
 pub fn check_hbitmap(h_bitmap: HBITMAP, mut bmi: BITMAPINFO,  hdc: HDC, width: u32, height: u32, depth: u32) -> Result<String, String> {
     if h_bitmap.is_invalid() {
         // Get the last error if the bitmap creation failed
@@ -0,0 +1,53 @@
+//! A throwaway `dbus-daemon` for tests that need a bus to talk to without touching the real session bus.
+use std::{fs, io::{BufRead, BufReader, Error}, process::{Child, Command, Stdio}};
+use tempfile::TempDir;
+
+const BUS_CONFIG: &str = r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-BUS Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>session</type>
+  <listen>unix:dir=DIR</listen>
+  <policy context="default">
+    <allow send_destination="*" eavesdrop="true"/>
+    <allow eavesdrop="true"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>"#;
+
+pub struct TestBus {
+    pub address: String,
+    daemon: Child,
+    _dir: TempDir,
+}
+impl TestBus {
+    /// Starts a private bus. Like `TestDisplay`, the tests that need one only run with `--ignored`, so a missing
+    /// `dbus-daemon` fails them.
+    pub fn start() -> Self {
+        Self::try_start().unwrap_or_else(|e| panic!("No test bus: {}", e))
+    }
+
+    fn try_start() -> Result<Self, Error> {
+        let dir = tempfile::tempdir()?;
+        let config = dir.path().join("bus.conf");
+        fs::write(&config, BUS_CONFIG.replace("DIR", &dir.path().to_string_lossy()))?;
+        let mut daemon = Command::new("dbus-daemon")
+            .arg(format!("--config-file={}", config.display()))
+            .args(["--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut address)?;
+        Ok(Self { address: address.trim().to_string(), daemon, _dir: dir })
+    }
+
+    pub fn connect(&self) -> zbus::blocking::Connection {
+        zbus::blocking::connection::Builder::address(self.address.as_str()).unwrap().build().unwrap()
+    }
+}
+impl Drop for TestBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
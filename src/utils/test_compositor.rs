@@ -0,0 +1,55 @@
+//! A throwaway headless Sway for tests that need a Wayland compositor with layer-shell, without a GPU or a screen.
+use std::{fs, io::{Error, ErrorKind}, path::PathBuf, process::{Child, Command, Stdio}, thread, time::Duration};
+use tempfile::TempDir;
+
+pub struct TestCompositor {
+    /// The compositor's socket, to connect to instead of `WAYLAND_DISPLAY`.
+    pub socket: PathBuf,
+    compositor: Child,
+    _dir: TempDir,
+}
+impl TestCompositor {
+    /// Starts a private compositor. Like `TestDisplay`, the tests that need one only run with `--ignored`, so a missing
+    /// `sway` fails them.
+    pub fn start() -> Self {
+        Self::try_start().unwrap_or_else(|e| panic!("No test compositor: {}", e))
+    }
+
+    fn try_start() -> Result<Self, Error> {
+        let dir = tempfile::tempdir()?;
+        let config = dir.path().join("sway.conf");
+        fs::write(&config, "")?;
+        let mut compositor = Command::new("sway")
+            .arg("--config").arg(&config)
+            .env("XDG_RUNTIME_DIR", dir.path())
+            .env("WLR_BACKENDS", "headless")
+            .env("WLR_RENDERER", "pixman")
+            .env("WLR_LIBINPUT_NO_DEVICES", "1")
+            .env_remove("WAYLAND_DISPLAY")
+            .env_remove("DISPLAY")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        for _ in 0..50 {
+            let socket = fs::read_dir(dir.path())?.flatten().map(|entry| entry.path()).find(|path| {
+                path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("wayland-"))
+                    && path.extension().is_none()
+            });
+            if let Some(socket) = socket {
+                return Ok(Self { socket, compositor, _dir: dir });
+            }
+            if let Some(status) = compositor.try_wait()? {
+                return Err(Error::other(format!("sway exited with {}", status)));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        let _ = compositor.kill();
+        Err(Error::new(ErrorKind::TimedOut, "sway never came up"))
+    }
+}
+impl Drop for TestCompositor {
+    fn drop(&mut self) {
+        let _ = self.compositor.kill();
+        let _ = self.compositor.wait();
+    }
+}
@@ -0,0 +1,47 @@
+//! A throwaway `Xvfb` for tests that need an X server, without a GPU or touching the real display.
+use std::{io::{Error, ErrorKind}, path::Path, process::{Child, Command, Stdio}, sync::atomic::{AtomicU32, Ordering},
+    thread, time::Duration};
+
+static NEXT: AtomicU32 = AtomicU32::new(0);
+
+pub struct TestDisplay {
+    /// What goes in `DISPLAY`, e.g. `:123`.
+    pub name: String,
+    server: Child,
+}
+impl TestDisplay {
+    /// Starts a private X server. The tests that need one are `#[ignore]`d, so by the time this runs someone asked for
+    /// them with `--ignored`, and a missing `Xvfb` is a failure rather than a quiet pass.
+    pub fn start() -> Self {
+        Self::try_start().unwrap_or_else(|e| panic!("No test display: {}", e))
+    }
+
+    fn try_start() -> Result<Self, Error> {
+        // Spread out by pid so parallel test runs don't fight over display numbers.
+        let number = 100 + std::process::id() % 800 + NEXT.fetch_add(1, Ordering::Relaxed);
+        let mut server = Command::new("Xvfb")
+            .arg(format!(":{}", number))
+            .args(["-screen", "0", "1280x720x24", "-nolisten", "tcp"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let socket = format!("/tmp/.X11-unix/X{}", number);
+        for _ in 0..50 {
+            if Path::new(&socket).exists() {
+                return Ok(Self { name: format!(":{}", number), server });
+            }
+            if let Some(status) = server.try_wait()? {
+                return Err(Error::other(format!("Xvfb exited with {}", status)));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        let _ = server.kill();
+        Err(Error::new(ErrorKind::TimedOut, "Xvfb never came up"))
+    }
+}
+impl Drop for TestDisplay {
+    fn drop(&mut self) {
+        let _ = self.server.kill();
+        let _ = self.server.wait();
+    }
+}
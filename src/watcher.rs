@@ -0,0 +1,235 @@
+use crate::{config::{self, Focus, FocusPolicy}, media::*, props::*, rules::*};
+use std::{collections::{HashMap, HashSet}, io::Error, result::Result, sync::mpsc};
+
+/// A track worth a toast, and how to toast it.
+#[derive(Debug, Clone)]
+pub struct NewTrack {
+    pub session: String,
+    pub props: SpectreProps,
+    pub settings: ToastSettings,
+}
+
+/// Keeps track of what every session of a `MediaSource` is playing, and works out when that's worth a toast.
+///
+/// A session only gets toasted when it shows up or when its track actually changes (see `SpectreProps::same_track`),
+/// so pausing, seeking or a late thumbnail won't spam toasts for the song that's already playing.
+/// Tracks the config's rules and filters don't allow are still kept track of, they just never get toasted.
+/// Paused and stopped sessions are left alone entirely, so a track that changed while paused gets its toast once it plays.
+/// The same goes for sessions that don't have focus (see `FocusPolicy`), which get their toast once they do.
+pub struct Watcher<S: MediaSource> {
+    source: S,
+    playing: HashMap<String, SpectreProps>,
+    /// The session `FocusPolicy::Recent` is following.
+    recent: Option<String>,
+    /// Sessions of the pinned app, for `FocusPolicy::Pinned`.
+    pinned: HashSet<String>,
+    /// Used instead of the config's `[focus]` when set.
+    focus: Option<Focus>,
+}
+
+impl<S: MediaSource> Watcher<S> {
+    pub fn new(source: S) -> Self {
+        Self { source, playing: HashMap::new(), recent: None, pinned: HashSet::new(), focus: None }
+    }
+
+    /// Sticks to `focus` instead of following the config.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn with_focus(mut self, focus: Focus) -> Self {
+        self.focus = Some(focus);
+        self
+    }
+
+    /// Whether `session` is the current one. If the source can't say, everything is.
+    fn os_focused(&self, session: &str) -> bool {
+        match self.source.current_session() {
+            Ok(Some(current)) => current == session,
+            _ => true,
+        }
+    }
+
+    /// Whether `session` has focus under `focus`, updating who has it along the way.
+    fn focused(&mut self, event: &MediaEvent, session: &str, props: &SpectreProps, focus: &Focus) -> bool {
+        match focus.policy {
+            FocusPolicy::All => true,
+            FocusPolicy::Os => self.os_focused(session),
+            FocusPolicy::Recent => {
+                if self.recent.is_none() {
+                    self.recent = self.source.current_session().ok().flatten();
+                }
+                // Sessions that were already around when we started don't get to steal focus just by showing up.
+                if self.recent.is_none() || !matches!(event, MediaEvent::SessionAdded(_)) {
+                    self.recent = Some(session.to_string());
+                }
+                self.recent.as_deref() == Some(session)
+            }
+            FocusPolicy::Pinned => {
+                let pinned = focus.pin.as_ref().is_some_and(|pin| {
+                    session.to_lowercase().contains(&pin.to_lowercase()) || props.source.matches(pin)
+                });
+                if pinned {
+                    self.pinned.insert(session.to_string());
+                }
+                if self.pinned.is_empty() { self.os_focused(session) } else { pinned }
+            }
+        }
+    }
+
+    /// Updates the watcher with a single event from the source.
+    ///
+    /// # Returns
+    /// The new track if the event means it should be toasted, otherwise `None`.
+    pub fn handle(&mut self, event: &MediaEvent) -> Option<NewTrack> {
+        let session = match event {
+            MediaEvent::SessionRemoved(session) => {
+                self.playing.remove(session);
+                self.pinned.remove(session);
+                if self.recent.as_ref() == Some(session) {
+                    self.recent = None;
+                }
+                return None;
+            }
+            MediaEvent::SessionAdded(session)
+            | MediaEvent::PropsChanged(session)
+            | MediaEvent::PlaybackChanged(session) => session,
+        };
+        // The session can be gone by the time we get to the event, nothing to toast then.
+        let props = self.source.props(session).ok()?;
+        if !props.status.is_active() {
+            return None;
+        }
+        let config = config::get();
+        let focus = self.focus.clone().unwrap_or_else(|| config.focus.clone());
+        if !self.focused(event, session, &props, &focus) {
+            return None;
+        }
+        let changed = match self.playing.get(session) {
+            Some(old) => !old.same_track(&props),
+            None => true,
+        };
+        self.playing.insert(session.clone(), props.clone());
+        if !changed {
+            return None;
+        }
+        let verdict = Verdict::of(&config.rules, session, &props);
+        verdict.shows(&config, session, &props)
+            .then(|| NewTrack { session: session.clone(), settings: verdict.settings(&config, &props), props })
+    }
+
+    /// Subscribes to the source and calls `on_track` every time a session starts playing a new track.
+    ///
+    /// Blocks for as long as the source keeps sending events, which for the real backends is forever.
+    pub fn run(&mut self, mut on_track: impl FnMut(NewTrack)) -> Result<(), Error> {
+        let (tx, rx) = mpsc::channel();
+        self.source.subscribe(tx)?;
+        for event in rx {
+            if let Some(track) = self.handle(&event) {
+                on_track(track);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::script::{tests::fixture, Script, ScriptedSource};
+    use std::path::Path;
+
+    /// Plays the whole script through a watcher in lockstep, collecting what would have been toasted.
+    fn toasts(source: &ScriptedSource) -> Vec<(String, String)> {
+        toasts_with(source, Focus::default())
+    }
+    fn toasts_with(source: &ScriptedSource, focus: Focus) -> Vec<(String, String)> {
+        let mut watcher = Watcher::new(source).with_focus(focus);
+        std::iter::from_fn(|| source.advance())
+            .filter_map(|event| watcher.handle(&event))
+            .map(|track| (track.session, track.props.title))
+            .collect()
+    }
+
+    #[test]
+    fn toasts_only_new_tracks() {
+        let source = ScriptedSource::load(&fixture("track_changes.toml")).unwrap();
+        // The playback change and the late thumbnail for Ghost Town don't get their own toasts.
+        assert_eq!(toasts(&source), vec![
+            ("spotify".to_string(), "Ghost Town".to_string()),
+            ("browser".to_string(), "Some Video".to_string()),
+            ("spotify".to_string(), "Violent Crimes".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn forgets_removed_sessions() {
+        let script: Script = toml::from_str(r#"
+            [[step]]
+            session = "mpv"
+            props = { title = "Loop" }
+            [[step]]
+            session = "mpv"
+            removed = true
+            [[step]]
+            session = "mpv"
+            props = { title = "Loop" }
+        "#).unwrap();
+        let source = ScriptedSource::new(script, Path::new("."));
+        assert_eq!(toasts(&source).len(), 2);
+    }
+
+    #[test]
+    fn waits_for_paused_sessions() {
+        let script: Script = toml::from_str(r#"
+            [[step]]
+            session = "mpv"
+            props = { title = "Loop", status = "playing" }
+            [[step]]
+            session = "mpv"
+            status = "paused"
+            [[step]]
+            session = "mpv"
+            props = { title = "Skipped To", status = "paused" }
+            [[step]]
+            session = "mpv"
+            status = "playing"
+            [[step]]
+            session = "vlc"
+            props = { title = "Opened Paused", status = "stopped" }
+        "#).unwrap();
+        let source = ScriptedSource::new(script, Path::new("."));
+        assert_eq!(toasts(&source), vec![
+            ("mpv".to_string(), "Loop".to_string()),
+            ("mpv".to_string(), "Skipped To".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn only_focused_sessions() {
+        let script: Script = toml::from_str(r#"
+            [[step]]
+            session = "spotify"
+            props = { title = "Song A", status = "playing" }
+            [[step]]
+            session = "browser"
+            props = { title = "Video 1", status = "playing" }
+            [[step]]
+            session = "spotify"
+            props = { title = "Song B", status = "playing" }
+            [[step]]
+            session = "browser"
+            props = { title = "Video 2", status = "playing" }
+        "#).unwrap();
+        let run = |policy, pin: Option<&str>| {
+            let source = ScriptedSource::new(script.clone(), Path::new("."));
+            let toasts = toasts_with(&source, Focus { policy, pin: pin.map(str::to_string) });
+            toasts.into_iter().map(|(_, title)| title).collect::<Vec<_>>()
+        };
+        // The browser started playing last, so it's current from then on.
+        assert_eq!(run(FocusPolicy::Os, None), ["Song A", "Video 1", "Video 2"]);
+        // Showing up doesn't take focus, changing track does.
+        assert_eq!(run(FocusPolicy::Recent, None), ["Song A", "Song B", "Video 2"]);
+        assert_eq!(run(FocusPolicy::Pinned, Some("spotify")), ["Song A", "Song B"]);
+        // Nothing to pin, so it's the same as Os.
+        assert_eq!(run(FocusPolicy::Pinned, Some("vlc")), run(FocusPolicy::Os, None));
+        assert_eq!(run(FocusPolicy::All, None).len(), 4);
+    }
+}